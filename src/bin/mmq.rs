@@ -0,0 +1,309 @@
+// mmq , looks inside the shared memory queues without touching them
+//
+//   mmq <queue file> [--last N | --unconsumed] [--json] [--header]
+//
+// the record type is picked from the magic in the header , so any of
+// /tmp/MarketMakerOrders , /tmp/MarketMakerFills , /tmp/MarketMakerFeed , /tmp/MessageFromApiToMM works
+
+use std::process::ExitCode;
+
+use serde::Serialize;
+
+use market_maker::shm::{
+    feed_queue_mm::MarketMakerFeed,
+    fill_queue_mm::MarketMakerFill,
+    order_queue_mm::MmOrder,
+    queue::{HeaderSnapshot, QueueError, QueueView, ShmRecord},
+    response_queue_mm::MessageFromApi,
+};
+
+const USAGE: &str = "usage: mmq <queue file> [--last N | --unconsumed] [--json] [--header]
+  --last N       decode the N most recently enqueued records (default 10)
+  --unconsumed   decode every record between tail and head
+  --json         one JSON object per line instead of a table
+  --header       only print the header";
+
+enum Range {
+    Last(u64),
+    Unconsumed,
+}
+
+struct Args {
+    path: String,
+    range: Range,
+    json: bool,
+    header_only: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut path = None;
+    let mut range = Range::Last(10);
+    let mut json = false;
+    let mut header_only = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--last" => {
+                let n = args.next().ok_or("--last needs a count")?;
+                range = Range::Last(n.parse().map_err(|_| format!("bad count for --last: {}", n))?);
+            }
+            "--unconsumed" => range = Range::Unconsumed,
+            "--json" => json = true,
+            "--header" => header_only = true,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            other if other.starts_with("--") => return Err(format!("unknown flag {}\n{}", other, USAGE)),
+            other => {
+                if path.replace(other.to_string()).is_some() {
+                    return Err(format!("only one queue file at a time\n{}", USAGE));
+                }
+            }
+        }
+    }
+
+    Ok(Args {
+        path: path.ok_or(USAGE)?,
+        range,
+        json,
+        header_only,
+    })
+}
+
+// column names and cell values for the table output
+trait Columns {
+    const KIND: &'static str;
+    const NAMES: &'static [&'static str];
+    fn cells(&self) -> Vec<String>;
+}
+
+impl Columns for MmOrder {
+    const KIND: &'static str = "MmOrder";
+    const NAMES: &'static [&'static str] = &[
+        "order_id", "client_id", "price", "timestamp", "shares_qty", "symbol", "side", "order_type", "status",
+    ];
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.order_id.to_string(),
+            self.client_id.to_string(),
+            self.price.to_string(),
+            self.timestamp.to_string(),
+            self.shares_qty.to_string(),
+            self.symbol.to_string(),
+            self.side.to_string(),
+            self.order_type.to_string(),
+            self.status.to_string(),
+        ]
+    }
+}
+
+impl Columns for MarketMakerFill {
+    const KIND: &'static str = "MarketMakerFill";
+    const NAMES: &'static [&'static str] = &[
+        "order_id_mm_order", "timestamp", "seq", "fill_price", "fill_quantity", "symbol", "side_of_mm_order",
+    ];
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.order_id_mm_order.to_string(),
+            self.timestamp.to_string(),
+            self.seq.to_string(),
+            self.fill_price.to_string(),
+            self.fill_quantity.to_string(),
+            self.symbol.to_string(),
+            self.side_of_mm_order.to_string(),
+        ]
+    }
+}
+
+impl Columns for MarketMakerFeed {
+    const KIND: &'static str = "MarketMakerFeed";
+    const NAMES: &'static [&'static str] = &[
+        "timestamp", "seq", "last_traded_price", "best_bid", "best_ask", "best_bid_qty", "best_ask_qty", "symbol",
+    ];
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.timestamp.to_string(),
+            self.seq.to_string(),
+            self.last_traded_price.to_string(),
+            self.best_bid.to_string(),
+            self.best_ask.to_string(),
+            self.best_bid_qty.to_string(),
+            self.best_ask_qty.to_string(),
+            self.symbol.to_string(),
+        ]
+    }
+}
+
+impl Columns for MessageFromApi {
+    const KIND: &'static str = "MessageFromApi";
+    const NAMES: &'static [&'static str] = &[
+        "order_id", "client_id", "ipo_price", "timestamp", "shares_qty", "symbol", "side", "message_type", "reject_reason",
+    ];
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.order_id.to_string(),
+            self.client_id.to_string(),
+            self.ipo_price.to_string(),
+            self.timestamp.to_string(),
+            self.shares_qty.to_string(),
+            self.symbol.to_string(),
+            self.side.to_string(),
+            self.message_type.to_string(),
+            self.reject_reason.to_string(),
+        ]
+    }
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a, T: Serialize> {
+    index: u64,
+    slot: u64,
+    consumed: bool,
+    kind: &'a str,
+    record: T,
+}
+
+fn print_header(path: &str, kind: &str, header: &HeaderSnapshot, json: bool) {
+    let stats = &header.stats;
+    if json {
+        let value = serde_json::json!({
+            "file": path,
+            "kind": kind,
+            "magic": format!("{:#010x}", header.magic),
+            "version": header.version,
+            "record_size": header.record_size,
+            "capacity": stats.capacity,
+            "producer_head": header.producer_head,
+            "consumer_tail": header.consumer_tail,
+            "depth": stats.depth,
+            "high_watermark": stats.high_watermark,
+            "full_rejections": stats.full_rejections,
+            "producer_heartbeat_ns": stats.producer_heartbeat_ns,
+            "consumer_heartbeat_ns": stats.consumer_heartbeat_ns,
+        });
+        println!("{}", value);
+        return;
+    }
+
+    let silence = |age: Option<std::time::Duration>| match age {
+        Some(age) => format!("{:?} ago", age),
+        None => "never".to_string(),
+    };
+    println!("file            {}", path);
+    println!("kind            {}", kind);
+    println!("magic           {:#010x}", header.magic);
+    println!("version         {}", header.version);
+    println!("record_size     {}", header.record_size);
+    println!("capacity        {}", stats.capacity);
+    println!("producer_head   {}", header.producer_head);
+    println!("consumer_tail   {}", header.consumer_tail);
+    println!("depth           {}", stats.depth);
+    println!("high_watermark  {}", stats.high_watermark);
+    println!("full_rejections {}", stats.full_rejections);
+    println!("producer beat   {}", silence(stats.producer_silence()));
+    println!("consumer beat   {}", silence(stats.consumer_silence()));
+}
+
+fn dump<T: ShmRecord + Columns + Serialize>(view: &QueueView, args: &Args) -> Result<(), QueueError> {
+    view.check::<T>()?;
+    let header = view.header();
+    print_header(&args.path, T::KIND, &header, args.json);
+    if args.header_only {
+        return Ok(());
+    }
+
+    // a slot older than head - capacity has been overwritten , never go past that
+    let oldest = header.producer_head.saturating_sub(header.stats.capacity);
+    let start = match args.range {
+        Range::Last(n) => header.producer_head.saturating_sub(n),
+        Range::Unconsumed => header.consumer_tail,
+    }
+    .max(oldest);
+
+    let mut rows = Vec::new();
+    for index in start..header.producer_head {
+        let record = view.record::<T>(index)?;
+        let slot = index & (header.stats.capacity - 1);
+        let consumed = index < header.consumer_tail;
+        if args.json {
+            let line = JsonRecord { index, slot, consumed, kind: T::KIND, record };
+            println!("{}", serde_json::to_string(&line).unwrap_or_default());
+        } else {
+            let mut cells = vec![index.to_string(), slot.to_string(), if consumed { "y" } else { "" }.to_string()];
+            cells.extend(record.cells());
+            rows.push(cells);
+        }
+    }
+
+    if !args.json {
+        println!();
+        print_table(T::NAMES, &rows);
+    }
+    Ok(())
+}
+
+fn print_table(names: &[&str], rows: &[Vec<String>]) {
+    let mut titles = vec!["index", "slot", "consumed"];
+    titles.extend_from_slice(names);
+
+    let mut widths: Vec<usize> = titles.iter().map(|title| title.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let line = |cells: &mut dyn Iterator<Item = &str>| {
+        cells
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:>width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+    };
+    println!("{}", line(&mut titles.iter().copied()));
+    for row in rows {
+        println!("{}", line(&mut row.iter().map(String::as_str)));
+    }
+    if rows.is_empty() {
+        println!("(no records)");
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    let view = match QueueView::open(&args.path) {
+        Ok(view) => view,
+        Err(e) => {
+            eprintln!("{}: {}", args.path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let magic = view.header().magic;
+    let result = if magic == MmOrder::MAGIC {
+        dump::<MmOrder>(&view, &args)
+    } else if magic == MarketMakerFill::MAGIC {
+        dump::<MarketMakerFill>(&view, &args)
+    } else if magic == MarketMakerFeed::MAGIC {
+        dump::<MarketMakerFeed>(&view, &args)
+    } else if magic == MessageFromApi::MAGIC {
+        dump::<MessageFromApi>(&view, &args)
+    } else {
+        eprintln!("{}: unknown magic {:#010x} , not one of the market maker queues", args.path, magic);
+        return ExitCode::FAILURE;
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}: {}", args.path, e);
+            ExitCode::FAILURE
+        }
+    }
+}
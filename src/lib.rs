@@ -1,2 +1,3 @@
 pub mod shm;
-pub mod mmbot;
\ No newline at end of file
+pub mod mmbot;
+pub mod transport;
@@ -1,9 +1,59 @@
-use market_maker::{mmbot::market_maker::MarketMaker, shm::{order_queue_mm::MarketMakerOrderQueue, response_queue_mm::MessageFromApiQueue}};
+use market_maker::{mmbot::{client_id::next_epoch, config::MmConfig, market_maker::MarketMaker, signals}, shm::{order_queue_mm::{MarketMakerOrderQueue, ORDER_QUEUE_CAPACITY}, response_queue_mm::{MessageFromApiQueue, API_QUEUE_CAPACITY}}};
 
 fn main() {
-    let _ = MarketMakerOrderQueue::create("/tmp/MarketMakerOrders").expect("failed to open market maker order queue");
-    let _ = MessageFromApiQueue::create("/tmp/MessageFromApiToMM").expect("failed to open the message queue api -> mm");
-    let _ = MarketMaker::new();
-   // run mm
-}
+    // optional json config as the first argument , built in defaults otherwise
+    let config = match std::env::args().nth(1) {
+        Some(path) => match MmConfig::load(&path) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("{} : {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => MmConfig::default(),
+    };
+
+    // SIGUSR1 pulls the kill switch , SIGINT / SIGTERM cancel everything and exit
+    for signal in [libc::SIGUSR1, libc::SIGINT, libc::SIGTERM] {
+        if let Err(e) = signals::install(signal) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // a fresh client id epoch , ids of orders an earlier run left on the engine cant collide with ours
+    let epoch = match &config.epoch_file {
+        Some(path) => match next_epoch(path) {
+            Ok(epoch) => epoch,
+            Err(e) => {
+                eprintln!("{} : {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => 0,
+    };
 
+    // reuse the queues if they survived a restart , orders still in flight are kept
+    let (_, reinit) = MarketMakerOrderQueue::open_or_create("/tmp/MarketMakerOrders", ORDER_QUEUE_CAPACITY).expect("failed to open market maker order queue");
+    if let Some(reinit) = reinit {
+        eprintln!("/tmp/MarketMakerOrders : {}", reinit);
+    }
+    let (_, reinit) = MessageFromApiQueue::open_or_create("/tmp/MessageFromApiToMM", API_QUEUE_CAPACITY).expect("failed to open the message queue api -> mm");
+    if let Some(reinit) = reinit {
+        eprintln!("/tmp/MessageFromApiToMM : {}", reinit);
+    }
+    let mut mm = match MarketMaker::open_shm() {
+        Ok(mm) => mm.with_config(config).with_epoch(epoch),
+        Err(e) => {
+            eprintln!("failed to open the engine queues : {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // run mm , returns once a signal asked it to stop and the books are empty or the wait ran out
+    let report = mm.run_market_maker();
+    if let Err(e) = report.write(mm.config.shutdown_report.as_deref()) {
+        eprintln!("failed to write the shutdown report : {}", e);
+    }
+    std::process::exit(report.exit_code());
+}
@@ -0,0 +1,59 @@
+use std::path::Path;
+
+// client order ids are unique across symbols and restarts :
+//
+//   | epoch : 16 | symbol : 16 | seq : 32 |
+//
+// the epoch is bumped in a small file every time the bot starts , so ids of orders a previous run left
+// on the engine never collide with ours , the symbol makes the id route an ack or a reject on its own
+// epoch 0 is never handed out by the file , it is what a bot without one (tests , replay) uses
+//
+// the 16 bit symbol field limits which symbols the bot can trade : an add symbol message for an id above
+// MAX_CLIENT_ID_SYMBOL is rejected (logged and counted in TickSummary::symbols_rejected)
+
+pub const MAX_CLIENT_ID_SYMBOL: u32 = u16::MAX as u32;
+
+const SYMBOL_SHIFT: u32 = 32;
+const EPOCH_SHIFT: u32 = 48;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientId {
+    pub epoch: u16,
+    pub symbol: u16,
+    pub seq: u32,
+}
+
+impl ClientId {
+    pub fn encode(self) -> u64 {
+        (self.epoch as u64) << EPOCH_SHIFT | (self.symbol as u64) << SYMBOL_SHIFT | self.seq as u64
+    }
+
+    pub fn decode(raw: u64) -> Self {
+        Self {
+            epoch: (raw >> EPOCH_SHIFT) as u16,
+            symbol: (raw >> SYMBOL_SHIFT) as u16,
+            seq: raw as u32,
+        }
+    }
+}
+
+/// Reads the last epoch from `path` , stores the next one and returns it.
+/// A missing file starts at 1 , after u16::MAX it wraps back to 1.
+pub fn next_epoch<P: AsRef<Path>>(path: P) -> std::io::Result<u16> {
+    let path = path.as_ref();
+    let last = match std::fs::read_to_string(path) {
+        Ok(text) => text.trim().parse::<u16>().map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{} : {}", path.display(), e))
+        })?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e),
+    };
+    let epoch = if last == u16::MAX { 1 } else { last + 1 };
+
+    // written next to it and renamed over , a crash never leaves a half written epoch
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, format!("{}\n", epoch))?;
+    std::fs::File::open(&tmp)?.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(epoch)
+}
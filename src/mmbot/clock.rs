@@ -0,0 +1,72 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// every timer in the bot runs off a Clock instead of Instant::now() , times are plain
+// monotonic nanoseconds (u64) so replay and tests can feed their own
+
+pub trait Clock {
+    /// monotonic nanoseconds , only differences between two readings mean anything
+    fn now_nanos(&self) -> u64;
+}
+
+/// time between two clock readings , 0 if `since` is somehow ahead of `now`
+#[inline(always)]
+pub fn elapsed(now: u64, since: u64) -> Duration {
+    Duration::from_nanos(now.saturating_sub(since))
+}
+
+/// Wall clock for the live bot , nanoseconds since it was created.
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    origin: Instant,
+}
+
+impl MonotonicClock {
+    pub fn new() -> Self {
+        Self { origin: Instant::now() }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MonotonicClock {
+    #[inline(always)]
+    fn now_nanos(&self) -> u64 {
+        self.origin.elapsed().as_nanos() as u64
+    }
+}
+
+/// Manually advanced clock for replay and tests , only moves when told to.
+/// Clones share the same time , keep one to drive the clock the market maker holds.
+#[derive(Debug, Clone, Default)]
+pub struct SimClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl SimClock {
+    pub fn new(start_nanos: u64) -> Self {
+        Self { nanos: Arc::new(AtomicU64::new(start_nanos)) }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.nanos.fetch_add(by.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// jumps straight to `nanos` , replay uses the recorded timestamps
+    /// going backwards is ignored , the clock stays monotonic
+    pub fn set(&self, nanos: u64) {
+        self.nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+}
+
+impl Clock for SimClock {
+    #[inline(always)]
+    fn now_nanos(&self) -> u64 {
+        self.nanos.load(Ordering::Relaxed)
+    }
+}
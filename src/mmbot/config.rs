@@ -0,0 +1,602 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use market_maker_rs::dec;
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::mmbot::constants::{
+    BASE_SIZE_BOOTSTRAP, BASE_SIZE_CAPPED, BASE_SIZE_STRESSED, BOOTSTRAP_LEVELS, BOOTSTRAP_SIZE_DECAY, BOOTSTRAP_SPREAD_PCT, CANCEL_ACK_TIMEOUT,
+    CAPPED_LEVELS, CAPPED_SIZE_DECAY, MAX_SPREAD_IN_TICKS_TO_EXIT_BOOTSTRAP, STRESSED_SIZE_DECAY, STRESSED_VOLATILITY,
+    EPOCH_FILE, GHOST_ORDER_EXPIRY, INVENTORY_CANCELLATION_TRIGGER_AMNT, MAX_CANCEL_RETRIES, NEW_ACK_TIMEOUT,
+    INVENTORY_CAP, LIQUIDITY_K, LOT_SIZE, MANAGEMENT_CYCLE_GAP, MAX_ALLOWED_NEG_AGGREGATE_PNL, MAX_ALLOWED_NEG_REALISED_PNL, MAX_ALLOWED_NEG_TOTAL_PNL, MAX_BOOK_MULT,
+    MAX_DISTANCE_IN_TICKS_TO_CANCEL_BOOTSTRAP, MAX_DISTANCE_IN_TICKS_TO_CANCEL_NORMAL,
+    MAX_DISTANCE_IN_TICKS_TO_CANCEL_STRESSED, MAX_ORDER_AGE, MAX_SIZE_FOR_ORDER, MIN_PROFITABLE_SPREAD_IN_TICKS,
+    MIN_PROFITABLE_SPREAD_IN_TICKS_BOOTSTRAP, MIN_PROFITABLE_SPREAD_IN_TICKS_NORMAL,
+    MIN_PROFITABLE_SPREAD_IN_TICKS_STRESSED, MIN_SAMPLES_TO_EXIT_BOOTSTRAP, MIN_TRADES_TO_EXIT_BOOTSTRAP,
+    MIN_ORDER_QTY, MIN_VOLUME_TO_EXIT_BOOTSTRAP, NORMAL_LEVELS, NORMAL_SIZE_DECAY, QUOTING_GAP, RECONCILE_GAP, RISK_AVERSION,
+    SAMPLE_GAP, SHUTDOWN_TIMEOUT, STRESSED_LEVELS, STRESSED_SPREAD_MULT, TARGET_INVENTORY, TICK_SIZE, TIME_TO_TERMINAL, VOLITILTY_CALC_GAP,
+};
+
+// runtime tuning , loaded from a json file at startup
+// every field is optional in the file , anything left out keeps the value from constants.rs
+// durations are written in milliseconds (the `_ms` keys) , decimals as numbers or strings
+//
+// per symbol overrides go under "symbols" keyed by symbol id , each one only lists the fields it
+// changes and is laid over "defaults" (nested blocks field by field)
+//
+// {
+//   "management_cycle_gap_ms": 250,
+//   "defaults": { "tick_size": "0.05", "inventory_cap": 500 },
+//   "symbols": { "7": { "tick_size": "1", "lot_size": 5, "normal": { "max_distance_in_ticks": 4 } } }
+// }
+
+// deeper ladders than this are almost certainly a typo
+pub const MAX_LADDER_LEVELS: usize = 32;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(String),
+    Parse(String),
+    Invalid(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read(e) => write!(f, "Failed to read config: {}", e),
+            ConfigError::Parse(e) => write!(f, "Failed to parse config: {}", e),
+            ConfigError::Invalid(e) => write!(f, "Invalid config: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Bot wide settings plus the quoting parameters every symbol starts from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MmConfig {
+    // how often the mid is pushed into the rolling price window
+    #[serde(rename = "sample_gap_ms", with = "duration_ms")]
+    pub sample_gap: Duration,
+    #[serde(rename = "volatility_calc_gap_ms", with = "duration_ms")]
+    pub volatility_calc_gap: Duration,
+    // cancel checks , mode update and requote
+    #[serde(rename = "management_cycle_gap_ms", with = "duration_ms")]
+    pub management_cycle_gap: Duration,
+
+    // kill switch : total pnl summed over every symbol , and a file whose appearance pulls it
+    pub max_allowed_neg_aggregate_pnl: Decimal,
+    pub kill_file: Option<PathBuf>,
+
+    // SIGINT / SIGTERM : wait this long for the cancel acks , then write the report (stdout if no path)
+    #[serde(rename = "shutdown_timeout_ms", with = "duration_ms")]
+    pub shutdown_timeout: Duration,
+    pub shutdown_report: Option<PathBuf>,
+
+    pub ack_timeouts: AckTimeouts,
+    // open orders query per symbol , also how long to wait for its answer
+    #[serde(rename = "reconcile_gap_ms", with = "duration_ms")]
+    pub reconcile_gap: Duration,
+
+    // bumped on every start , part of every client id , null reuses epoch 0 and ids repeat across restarts
+    pub epoch_file: Option<PathBuf>,
+
+    pub defaults: SymbolConfig,
+
+    // symbol id -> fields that differ from `defaults` , resolved with `for_symbol`
+    pub symbols: FxHashMap<u32, Map<String, Value>>,
+}
+
+/// How long orders may wait for their acks , see SymbolContext::check_ack_timeouts.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AckTimeouts {
+    // PendingNew without an accept or a reject
+    #[serde(rename = "new_ms", with = "duration_ms")]
+    pub new: Duration,
+    // PendingCancel without a cancel ack , the cancel is sent again
+    #[serde(rename = "cancel_ms", with = "duration_ms")]
+    pub cancel: Duration,
+    pub max_cancel_retries: u32,
+    // a stuck order is forgotten this long after we gave up on it
+    #[serde(rename = "ghost_expiry_ms", with = "duration_ms")]
+    pub ghost_expiry: Duration,
+}
+
+impl Default for AckTimeouts {
+    fn default() -> Self {
+        Self {
+            new: NEW_ACK_TIMEOUT,
+            cancel: CANCEL_ACK_TIMEOUT,
+            max_cancel_retries: MAX_CANCEL_RETRIES,
+            ghost_expiry: GHOST_ORDER_EXPIRY,
+        }
+    }
+}
+
+/// Quoting and risk parameters of one symbol , SymbolState keeps its own copy.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SymbolConfig {
+    pub tick_size: Decimal,
+    // every order quantity is a multiple of this and at least min_order_qty
+    pub lot_size: u32,
+    pub min_order_qty: u32,
+
+    // AS model , see calculate_optimal_quotes
+    pub risk_aversion: Decimal,
+    pub liquidity_k: Decimal,
+    pub time_to_terminal: u64,
+
+    // inventory
+    pub target_inventory: Decimal,
+    pub max_size_for_order: Decimal,
+    pub inventory_cap: Decimal,
+    pub max_book_mult: Decimal,
+    // fraction of inventory_cap at which orders adding to the position get cancelled
+    pub inventory_cancellation_trigger: Decimal,
+
+    // pnl caps , going below either one puts the symbol in emergency mode
+    pub max_allowed_neg_total_pnl: Decimal,
+    pub max_allowed_neg_realised_pnl: Decimal,
+
+    // cancel thresholds per mode
+    pub bootstrap: CancelThresholds,
+    pub normal: CancelThresholds,
+    pub stressed: CancelThresholds,
+    // used on depth updates , whatever the mode
+    pub min_profitable_spread_in_ticks: Decimal,
+
+    // exit bootstrap once all four are met
+    pub min_trades_to_exit_bootstrap: u64,
+    pub min_volume_to_exit_bootstrap: u64,
+    pub min_samples_to_exit_bootstrap: usize,
+    pub max_spread_in_ticks_to_exit_bootstrap: Decimal,
+
+    // volatility above this puts the symbol in stressed mode
+    pub stressed_volatility: Decimal,
+
+    // ladders
+    pub bootstrap_spread_pct: Decimal,
+    pub bootstrap_levels: usize,
+    pub base_size_bootstrap: u64,
+    // each level deeper is this fraction of the one before it
+    pub bootstrap_size_decay: f64,
+    pub normal_levels: usize,
+    pub normal_size_decay: f64,
+    pub stressed_spread_mult: Decimal,
+    pub stressed_levels: usize,
+    pub base_size_stressed: u64,
+    pub stressed_size_decay: f64,
+    pub capped_levels: usize,
+    pub base_size_capped: u64,
+    pub capped_size_decay: f64,
+
+    #[serde(rename = "quoting_gap_ms", with = "duration_ms")]
+    pub quoting_gap: Duration,
+    #[serde(rename = "max_order_age_ms", with = "duration_ms")]
+    pub max_order_age: Duration,
+}
+
+// no field level defaults here , a partial block would silently pick up another mode's values
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CancelThresholds {
+    // order further than this from the mid gets cancelled
+    pub max_distance_in_ticks: Decimal,
+    // spread tighter than this makes resting orders unprofitable
+    pub min_profitable_spread_in_ticks: Decimal,
+}
+
+impl Default for SymbolConfig {
+    fn default() -> Self {
+        Self {
+            tick_size: TICK_SIZE,
+            lot_size: LOT_SIZE,
+            min_order_qty: MIN_ORDER_QTY,
+            risk_aversion: RISK_AVERSION,
+            liquidity_k: LIQUIDITY_K,
+            time_to_terminal: TIME_TO_TERMINAL,
+            target_inventory: TARGET_INVENTORY,
+            max_size_for_order: MAX_SIZE_FOR_ORDER,
+            inventory_cap: INVENTORY_CAP,
+            max_book_mult: MAX_BOOK_MULT,
+            inventory_cancellation_trigger: INVENTORY_CANCELLATION_TRIGGER_AMNT,
+            max_allowed_neg_total_pnl: MAX_ALLOWED_NEG_TOTAL_PNL,
+            max_allowed_neg_realised_pnl: MAX_ALLOWED_NEG_REALISED_PNL,
+            bootstrap: CancelThresholds {
+                max_distance_in_ticks: MAX_DISTANCE_IN_TICKS_TO_CANCEL_BOOTSTRAP,
+                min_profitable_spread_in_ticks: MIN_PROFITABLE_SPREAD_IN_TICKS_BOOTSTRAP,
+            },
+            normal: CancelThresholds {
+                max_distance_in_ticks: MAX_DISTANCE_IN_TICKS_TO_CANCEL_NORMAL,
+                min_profitable_spread_in_ticks: MIN_PROFITABLE_SPREAD_IN_TICKS_NORMAL,
+            },
+            stressed: CancelThresholds {
+                max_distance_in_ticks: MAX_DISTANCE_IN_TICKS_TO_CANCEL_STRESSED,
+                min_profitable_spread_in_ticks: MIN_PROFITABLE_SPREAD_IN_TICKS_STRESSED,
+            },
+            min_profitable_spread_in_ticks: MIN_PROFITABLE_SPREAD_IN_TICKS,
+            min_trades_to_exit_bootstrap: MIN_TRADES_TO_EXIT_BOOTSTRAP,
+            min_volume_to_exit_bootstrap: MIN_VOLUME_TO_EXIT_BOOTSTRAP,
+            min_samples_to_exit_bootstrap: MIN_SAMPLES_TO_EXIT_BOOTSTRAP,
+            max_spread_in_ticks_to_exit_bootstrap: MAX_SPREAD_IN_TICKS_TO_EXIT_BOOTSTRAP,
+            stressed_volatility: STRESSED_VOLATILITY,
+            bootstrap_spread_pct: BOOTSTRAP_SPREAD_PCT,
+            bootstrap_levels: BOOTSTRAP_LEVELS,
+            base_size_bootstrap: BASE_SIZE_BOOTSTRAP,
+            bootstrap_size_decay: BOOTSTRAP_SIZE_DECAY,
+            normal_levels: NORMAL_LEVELS,
+            normal_size_decay: NORMAL_SIZE_DECAY,
+            stressed_spread_mult: STRESSED_SPREAD_MULT,
+            stressed_levels: STRESSED_LEVELS,
+            base_size_stressed: BASE_SIZE_STRESSED,
+            stressed_size_decay: STRESSED_SIZE_DECAY,
+            capped_levels: CAPPED_LEVELS,
+            base_size_capped: BASE_SIZE_CAPPED,
+            capped_size_decay: CAPPED_SIZE_DECAY,
+            quoting_gap: QUOTING_GAP,
+            max_order_age: MAX_ORDER_AGE,
+        }
+    }
+}
+
+impl Default for MmConfig {
+    fn default() -> Self {
+        Self {
+            sample_gap: SAMPLE_GAP,
+            volatility_calc_gap: VOLITILTY_CALC_GAP,
+            management_cycle_gap: MANAGEMENT_CYCLE_GAP,
+            max_allowed_neg_aggregate_pnl: MAX_ALLOWED_NEG_AGGREGATE_PNL,
+            kill_file: None,
+            shutdown_timeout: SHUTDOWN_TIMEOUT,
+            shutdown_report: None,
+            ack_timeouts: AckTimeouts::default(),
+            reconcile_gap: RECONCILE_GAP,
+            epoch_file: Some(PathBuf::from(EPOCH_FILE)),
+            defaults: SymbolConfig::default(),
+            symbols: FxHashMap::default(),
+        }
+    }
+}
+
+impl MmConfig {
+    /// reads and validates a json config file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|e| ConfigError::Read(e.to_string()))?;
+        Self::from_json(&text)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, ConfigError> {
+        let config: MmConfig = serde_json::from_str(text).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        non_zero("sample_gap_ms", self.sample_gap).map_err(ConfigError::Invalid)?;
+        non_zero("volatility_calc_gap_ms", self.volatility_calc_gap).map_err(ConfigError::Invalid)?;
+        non_zero("management_cycle_gap_ms", self.management_cycle_gap).map_err(ConfigError::Invalid)?;
+        non_zero("ack_timeouts.new_ms", self.ack_timeouts.new).map_err(ConfigError::Invalid)?;
+        non_zero("ack_timeouts.cancel_ms", self.ack_timeouts.cancel).map_err(ConfigError::Invalid)?;
+        non_zero("ack_timeouts.ghost_expiry_ms", self.ack_timeouts.ghost_expiry).map_err(ConfigError::Invalid)?;
+        non_zero("reconcile_gap_ms", self.reconcile_gap).map_err(ConfigError::Invalid)?;
+        if self.max_allowed_neg_aggregate_pnl >= dec!(0) {
+            return Err(ConfigError::Invalid("max_allowed_neg_aggregate_pnl must be below 0".to_string()));
+        }
+        self.defaults.validate().map_err(|e| ConfigError::Invalid(format!("defaults.{}", e)))?;
+        for &symbol in self.symbols.keys() {
+            self.for_symbol(symbol)?;
+        }
+        Ok(())
+    }
+
+    /// parameters for `symbol` : its overrides laid over `defaults` , just `defaults` if it has none
+    pub fn for_symbol(&self, symbol: u32) -> Result<SymbolConfig, ConfigError> {
+        let Some(overrides) = self.symbols.get(&symbol) else {
+            return Ok(self.defaults);
+        };
+
+        let mut merged = serde_json::to_value(self.defaults).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        merge(&mut merged, overrides);
+        let config: SymbolConfig = serde_json::from_value(merged)
+            .map_err(|e| ConfigError::Parse(format!("symbols.{}: {}", symbol, e)))?;
+        config
+            .validate()
+            .map_err(|e| ConfigError::Invalid(format!("symbols.{}.{}", symbol, e)))?;
+        Ok(config)
+    }
+}
+
+// lays `overrides` over `base` , nested objects are merged key by key instead of replaced
+fn merge(base: &mut Value, overrides: &Map<String, Value>) {
+    let Value::Object(base) = base else {
+        return;
+    };
+    for (key, value) in overrides {
+        match (base.get_mut(key), value) {
+            (Some(inner @ Value::Object(_)), Value::Object(nested)) => merge(inner, nested),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+impl SymbolConfig {
+    /// ladder size -> order quantity : at least min_order_qty , rounded down to whole lots , never below one lot
+    pub fn order_qty(&self, size: u64) -> u32 {
+        let lot = self.lot_size.max(1) as u64;
+        let qty = size.max(self.min_order_qty as u64).min(u32::MAX as u64);
+        ((qty / lot).max(1) * lot) as u32
+    }
+
+    /// returns the name of the first bad field and why
+    pub fn validate(&self) -> Result<(), String> {
+        positive("tick_size", self.tick_size)?;
+        if self.lot_size == 0 {
+            return Err("lot_size must be at least 1".to_string());
+        }
+        if self.risk_aversion < dec!(0) || self.liquidity_k < dec!(0) {
+            return Err("risk_aversion and liquidity_k must not be negative".to_string());
+        }
+        positive("max_size_for_order", self.max_size_for_order)?;
+        positive("inventory_cap", self.inventory_cap)?;
+        positive("max_book_mult", self.max_book_mult)?;
+        if self.target_inventory.abs() >= self.inventory_cap {
+            return Err("target_inventory must be inside +- inventory_cap".to_string());
+        }
+        if self.inventory_cancellation_trigger <= dec!(0) || self.inventory_cancellation_trigger > dec!(1) {
+            return Err("inventory_cancellation_trigger must be in (0 , 1]".to_string());
+        }
+        if self.max_allowed_neg_total_pnl >= dec!(0) || self.max_allowed_neg_realised_pnl >= dec!(0) {
+            return Err("max_allowed_neg_*_pnl must be below 0".to_string());
+        }
+        for (mode, thresholds) in [("bootstrap", &self.bootstrap), ("normal", &self.normal), ("stressed", &self.stressed)] {
+            if thresholds.max_distance_in_ticks <= dec!(0) || thresholds.min_profitable_spread_in_ticks < dec!(0) {
+                return Err(format!("{} thresholds must be positive", mode));
+            }
+        }
+        if self.min_profitable_spread_in_ticks < dec!(0) {
+            return Err("min_profitable_spread_in_ticks must not be negative".to_string());
+        }
+        positive("bootstrap_spread_pct", self.bootstrap_spread_pct)?;
+        for levels in [self.bootstrap_levels, self.normal_levels, self.stressed_levels, self.capped_levels] {
+            if levels == 0 || levels > MAX_LADDER_LEVELS {
+                return Err(format!("every *_levels must be between 1 and {}", MAX_LADDER_LEVELS));
+            }
+        }
+        if self.base_size_bootstrap == 0 || self.base_size_stressed == 0 || self.base_size_capped == 0 {
+            return Err("every base_size_* must be at least 1".to_string());
+        }
+        for (name, decay) in [
+            ("bootstrap_size_decay", self.bootstrap_size_decay),
+            ("normal_size_decay", self.normal_size_decay),
+            ("stressed_size_decay", self.stressed_size_decay),
+            ("capped_size_decay", self.capped_size_decay),
+        ] {
+            if !(decay > 0.0 && decay <= 1.0) {
+                return Err(format!("{} must be in (0 , 1]", name));
+            }
+        }
+        positive("max_spread_in_ticks_to_exit_bootstrap", self.max_spread_in_ticks_to_exit_bootstrap)?;
+        positive("stressed_volatility", self.stressed_volatility)?;
+        if self.stressed_spread_mult < dec!(1) {
+            return Err("stressed_spread_mult must be at least 1".to_string());
+        }
+        non_zero("quoting_gap_ms", self.quoting_gap)?;
+        non_zero("max_order_age_ms", self.max_order_age)
+    }
+}
+
+fn positive(name: &str, value: Decimal) -> Result<(), String> {
+    if value <= dec!(0) {
+        return Err(format!("{} must be above 0 , got {}", name, value));
+    }
+    Ok(())
+}
+
+fn non_zero(name: &str, value: Duration) -> Result<(), String> {
+    if value.is_zero() {
+        return Err(format!("{} must be above 0", name));
+    }
+    Ok(())
+}
+
+// durations as whole milliseconds in the file
+mod duration_ms {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(value.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+/// Parameters that can be changed at runtime with an UpdateParam message on the api queue.
+/// The id is what the message carries in its `client_id` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrategyParam {
+    RiskAversion = 1,
+    LiquidityK = 2,
+    TimeToTerminal = 3,
+    StressedSpreadMult = 4,
+    BootstrapSpreadPct = 5,
+    InventoryCap = 6,
+    MaxSizeForOrder = 7,
+    BootstrapLevels = 8,
+    NormalLevels = 9,
+    StressedLevels = 10,
+    CappedLevels = 11,
+    MaxAllowedNegTotalPnl = 12,
+    MaxAllowedNegRealisedPnl = 13,
+}
+
+// values travel as fixed point integers , 1.5 is sent as 15000
+pub const PARAM_VALUE_SCALE: u32 = 4;
+
+impl StrategyParam {
+    pub fn from_id(id: u64) -> Option<Self> {
+        let param = match id {
+            1 => StrategyParam::RiskAversion,
+            2 => StrategyParam::LiquidityK,
+            3 => StrategyParam::TimeToTerminal,
+            4 => StrategyParam::StressedSpreadMult,
+            5 => StrategyParam::BootstrapSpreadPct,
+            6 => StrategyParam::InventoryCap,
+            7 => StrategyParam::MaxSizeForOrder,
+            8 => StrategyParam::BootstrapLevels,
+            9 => StrategyParam::NormalLevels,
+            10 => StrategyParam::StressedLevels,
+            11 => StrategyParam::CappedLevels,
+            12 => StrategyParam::MaxAllowedNegTotalPnl,
+            13 => StrategyParam::MaxAllowedNegRealisedPnl,
+            _ => return None,
+        };
+        Some(param)
+    }
+}
+
+impl SymbolConfig {
+    /// copy of `self` with one parameter changed , Err if the result does not validate
+    pub fn with_param(&self, param: StrategyParam, value: Decimal) -> Result<SymbolConfig, String> {
+        let mut config = *self;
+        // counts and times have to be whole numbers
+        let whole = || -> Result<u64, String> {
+            if value.fract() != dec!(0) || value < dec!(0) {
+                return Err(format!("{:?} needs a whole non negative value , got {}", param, value));
+            }
+            value.to_u64().ok_or_else(|| format!("{:?} value {} is out of range", param, value))
+        };
+        match param {
+            StrategyParam::RiskAversion => config.risk_aversion = value,
+            StrategyParam::LiquidityK => config.liquidity_k = value,
+            StrategyParam::TimeToTerminal => config.time_to_terminal = whole()?,
+            StrategyParam::StressedSpreadMult => config.stressed_spread_mult = value,
+            StrategyParam::BootstrapSpreadPct => config.bootstrap_spread_pct = value,
+            StrategyParam::InventoryCap => config.inventory_cap = value,
+            StrategyParam::MaxSizeForOrder => config.max_size_for_order = value,
+            StrategyParam::BootstrapLevels => config.bootstrap_levels = whole()? as usize,
+            StrategyParam::NormalLevels => config.normal_levels = whole()? as usize,
+            StrategyParam::StressedLevels => config.stressed_levels = whole()? as usize,
+            StrategyParam::CappedLevels => config.capped_levels = whole()? as usize,
+            StrategyParam::MaxAllowedNegTotalPnl => config.max_allowed_neg_total_pnl = value,
+            StrategyParam::MaxAllowedNegRealisedPnl => config.max_allowed_neg_realised_pnl = value,
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid(text: &str) -> String {
+        match MmConfig::from_json(text) {
+            Err(ConfigError::Invalid(e)) => e,
+            other => panic!("expected an invalid config , got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn example_config_parses() {
+        let config = MmConfig::from_json(include_str!("../../mm_config.example.json")).unwrap();
+        assert_eq!(config.management_cycle_gap, Duration::from_millis(250));
+        assert_eq!(config.ack_timeouts.ghost_expiry, Duration::from_secs(30));
+        assert_eq!(config.defaults.tick_size, dec!(0.25));
+        assert_eq!(config.defaults.normal.max_distance_in_ticks, dec!(10));
+        assert_eq!(config.defaults.max_order_age, Duration::from_secs(900));
+        // the example spells out the built in values
+        assert_eq!(config.defaults.bootstrap_size_decay, BOOTSTRAP_SIZE_DECAY);
+        assert_eq!(config.defaults.stressed_size_decay, STRESSED_SIZE_DECAY);
+        assert_eq!(config.defaults.capped_size_decay, CAPPED_SIZE_DECAY);
+        assert_eq!(config.defaults.max_spread_in_ticks_to_exit_bootstrap, MAX_SPREAD_IN_TICKS_TO_EXIT_BOOTSTRAP);
+        assert_eq!(config.defaults.stressed_volatility, STRESSED_VOLATILITY);
+    }
+
+    #[test]
+    fn missing_fields_keep_the_built_in_values() {
+        let config = MmConfig::from_json(r#"{ "defaults": { "inventory_cap": 500 } }"#).unwrap();
+        assert_eq!(config.defaults.inventory_cap, dec!(500));
+        assert_eq!(config.defaults.tick_size, TICK_SIZE);
+        assert_eq!(config.management_cycle_gap, MANAGEMENT_CYCLE_GAP);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        for text in [
+            r#"{ "management_cycle_gap": 250 }"#,
+            r#"{ "defaults": { "tick": "0.05" } }"#,
+            r#"{ "ack_timeouts": { "new": 100 } }"#,
+            r#"{ "defaults": { "normal": { "max_distance_in_ticks": 4, "min_profitable_spread_in_ticks": 2, "levels": 3 } } }"#,
+        ] {
+            assert!(matches!(MmConfig::from_json(text), Err(ConfigError::Parse(_))), "{}", text);
+        }
+    }
+
+    #[test]
+    fn partial_cancel_thresholds_are_rejected() {
+        let text = r#"{ "defaults": { "normal": { "max_distance_in_ticks": 4 } } }"#;
+        assert!(matches!(MmConfig::from_json(text), Err(ConfigError::Parse(_))));
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        assert!(invalid(r#"{ "management_cycle_gap_ms": 0 }"#).contains("management_cycle_gap_ms"));
+        assert!(invalid(r#"{ "ack_timeouts": { "ghost_expiry_ms": 0 } }"#).contains("ghost_expiry_ms"));
+        assert!(invalid(r#"{ "max_allowed_neg_aggregate_pnl": 0 }"#).contains("max_allowed_neg_aggregate_pnl"));
+        assert!(invalid(r#"{ "defaults": { "lot_size": 0 } }"#).starts_with("defaults.lot_size"));
+        assert!(invalid(r#"{ "defaults": { "normal_levels": 33 } }"#).starts_with("defaults.every *_levels"));
+        assert!(invalid(r#"{ "defaults": { "target_inventory": 2000, "inventory_cap": 1000 } }"#).starts_with("defaults.target_inventory"));
+        assert!(invalid(r#"{ "defaults": { "normal_size_decay": 0 } }"#).starts_with("defaults.normal_size_decay"));
+        assert!(invalid(r#"{ "defaults": { "capped_size_decay": 1.5 } }"#).starts_with("defaults.capped_size_decay"));
+        assert!(invalid(r#"{ "defaults": { "stressed_volatility": 0 } }"#).starts_with("defaults.stressed_volatility"));
+        assert!(invalid(r#"{ "symbols": { "7": { "max_spread_in_ticks_to_exit_bootstrap": -1 } } }"#)
+            .starts_with("symbols.7.max_spread_in_ticks_to_exit_bootstrap"));
+    }
+
+    #[test]
+    fn symbol_overrides_are_laid_over_the_defaults() {
+        let config = MmConfig::from_json(
+            r#"{
+                "defaults": { "tick_size": "0.05", "inventory_cap": 500 },
+                "symbols": { "7": { "tick_size": "1", "lot_size": 5, "normal": { "max_distance_in_ticks": 4 } } }
+            }"#,
+        )
+        .unwrap();
+
+        let seven = config.for_symbol(7).unwrap();
+        assert_eq!(seven.tick_size, dec!(1));
+        assert_eq!(seven.lot_size, 5);
+        // fields the override leaves out come from defaults , nested blocks too
+        assert_eq!(seven.inventory_cap, dec!(500));
+        assert_eq!(seven.normal.max_distance_in_ticks, dec!(4));
+        assert_eq!(seven.normal.min_profitable_spread_in_ticks, config.defaults.normal.min_profitable_spread_in_ticks);
+        assert_eq!(seven.stressed, config.defaults.stressed);
+
+        // a symbol without overrides gets defaults as they are
+        assert_eq!(config.for_symbol(8).unwrap(), config.defaults);
+    }
+
+    #[test]
+    fn bad_symbol_overrides_are_rejected_with_the_symbol() {
+        let unknown = r#"{ "symbols": { "7": { "tick": "1" } } }"#;
+        match MmConfig::from_json(unknown) {
+            Err(ConfigError::Parse(e)) => assert!(e.starts_with("symbols.7:"), "{}", e),
+            other => panic!("expected a parse error , got {:?}", other),
+        }
+        assert!(invalid(r#"{ "symbols": { "7": { "lot_size": 0 } } }"#).starts_with("symbols.7.lot_size"));
+        assert!(invalid(r#"{ "symbols": { "7": { "normal": { "max_distance_in_ticks": -1 } } } }"#).starts_with("symbols.7.normal"));
+    }
+}
@@ -3,7 +3,8 @@ use std::time::Duration;
 use market_maker_rs::dec;
 use rust_decimal::Decimal;
 
-
+// the tuning values below are only the defaults of config::MmConfig , the bot reads them from there
+// (MAX_SYMBOLS , DRAIN_BATCH_SIZE , QUEUE_HEARTBEAT_GAP , KILL_FILE_CHECK_GAP and the REJECT_BACKOFF_* values are still used directly)
 
 // constant for the global tick size 
 pub const TICK_SIZE : Decimal = dec!(0.25);
@@ -14,6 +15,12 @@ pub const SAMPLE_GAP : Duration = Duration::from_millis(50);
 // max symbol 
 pub const MAX_SYMBOLS : usize = 100;
 
+// max records pulled off a shm queue per dequeue_batch call 
+pub const DRAIN_BATCH_SIZE : usize = 256;
+
+// how often the bot stamps its heartbeat into the shm queue headers 
+pub const QUEUE_HEARTBEAT_GAP : Duration = Duration::from_millis(100);
+
 // time gap after which volatility will be recalculated 
 pub const VOLITILTY_CALC_GAP  : Duration = Duration::from_millis(100);
 
@@ -44,6 +51,34 @@ pub const MAX_ORDER_AGE: Duration = Duration::from_secs(15*60);
 // PNL CAPS
 pub const MAX_ALLOWED_NEG_TOTAL_PNL : Decimal = dec!(-4000);
 pub const MAX_ALLOWED_NEG_REALISED_PNL : Decimal = dec!(-2000);
+// summed over every symbol , going below this pulls the kill switch 
+pub const MAX_ALLOWED_NEG_AGGREGATE_PNL : Decimal = dec!(-10000);
+
+// how often the kill switch flag file is looked for 
+pub const KILL_FILE_CHECK_GAP : Duration = Duration::from_millis(500);
+
+// rejects : this many in a row for the same reason stop quoting the symbol for REJECT_BACKOFF_BASE ,
+// doubling with every further backoff up to REJECT_BACKOFF_MAX 
+pub const REJECT_BACKOFF_AFTER : u32 = 3;
+pub const REJECT_BACKOFF_BASE : Duration = Duration::from_millis(500);
+pub const REJECT_BACKOFF_MAX : Duration = Duration::from_secs(30);
+
+// missing acks : a post is given NEW_ACK_TIMEOUT to be accepted or rejected , a cancel CANCEL_ACK_TIMEOUT
+// (then it is resent , up to MAX_CANCEL_RETRIES times) , orders still unanswered after that quarantine the symbol
+// and are forgotten GHOST_ORDER_EXPIRY later
+pub const NEW_ACK_TIMEOUT : Duration = Duration::from_secs(2);
+pub const CANCEL_ACK_TIMEOUT : Duration = Duration::from_secs(2);
+pub const MAX_CANCEL_RETRIES : u32 = 3;
+pub const GHOST_ORDER_EXPIRY : Duration = Duration::from_secs(30);
+
+// how often every symbol's orders are checked against the engine's open orders 
+pub const RECONCILE_GAP : Duration = Duration::from_secs(5);
+
+// the client id epoch is kept here across restarts , next to the queues 
+pub const EPOCH_FILE : &str = "/tmp/MarketMakerEpoch";
+
+// on shutdown , how long to wait for cancel acks and last fills before giving up 
+pub const SHUTDOWN_TIMEOUT : Duration = Duration::from_secs(5);
 
 
 // CANCEL THRESHOLDS FOR VAUROUS MODES 
@@ -74,6 +109,11 @@ pub const MIN_PROFITABLE_SPREAD_IN_TICKS : Decimal = dec!(2);
 pub const MIN_TRADES_TO_EXIT_BOOTSTRAP : u64 = 7;
 pub const MIN_VOLUME_TO_EXIT_BOOTSTRAP : u64 = 400;
 pub const MIN_SAMPLES_TO_EXIT_BOOTSTRAP : usize = 100;
+// and the spread has come in below this
+pub const MAX_SPREAD_IN_TICKS_TO_EXIT_BOOTSTRAP : Decimal = dec!(7);
+
+// volatility above this puts the symbol in stressed mode
+pub const STRESSED_VOLATILITY : Decimal = dec!(0.06);
 
 
 
@@ -84,9 +124,27 @@ pub const BOOTSTRAP_SPREAD_PCT : Decimal = dec!(0.05);
 pub const BOOTSTRAP_LEVELS : usize = 4;
 pub const NORMAL_LEVELS : usize = 6;
 pub const NORMAL_SIZE_DECAY : f64 = 0.85;
+// every level deeper quotes this fraction of the one before it
+pub const BOOTSTRAP_SIZE_DECAY : f64 = 0.85;
+pub const STRESSED_SIZE_DECAY : f64 = 0.80;
+pub const CAPPED_SIZE_DECAY : f64 = 0.90;
 pub const STRESSED_SPREAD_MULT : Decimal = dec!(2.5);
 pub const STRESSED_LEVELS : usize = 4;
 pub const CAPPED_LEVELS : usize = 2;
 
 
-pub const BASE_SIZE_BOOTSTRAP: u64 = 100; // configure acc to the shares that the mm will be alloted after the ipo
\ No newline at end of file
+pub const BASE_SIZE_BOOTSTRAP: u64 = 100; // configure acc to the shares that the mm will be alloted after the ipo
+
+
+// stressed and capped ladders quote smaller / bigger than bootstrap
+pub const BASE_SIZE_STRESSED: u64 = 50;
+pub const BASE_SIZE_CAPPED: u64 = 150;
+
+// order quantities are at least MIN_ORDER_QTY and a whole number of lots
+pub const LOT_SIZE: u32 = 1;
+pub const MIN_ORDER_QTY: u32 = 10;
+
+// AS model , still to be decided per listing 
+pub const RISK_AVERSION: Decimal = dec!(0);
+pub const LIQUIDITY_K: Decimal = dec!(0);
+pub const TIME_TO_TERMINAL: u64 = 0;
@@ -0,0 +1,95 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::shm::response_queue_mm::MessageFromApi;
+
+// global stop for quoting : once triggered every symbol is cancelled out and held in Emergency
+// and no post goes out again until the process restarts
+// the trigger is shared , any thread (or the signal poller) can pull it
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KillReason {
+    ControlMessage = 1,
+    Signal = 2,
+    FileFlag = 3,
+    PnlBreach = 4,
+    Manual = 5,
+    // SIGINT / SIGTERM , the bot exits once the books are empty
+    Shutdown = 6,
+}
+
+impl KillReason {
+    fn from_u8(value: u8) -> Option<Self> {
+        let reason = match value {
+            1 => KillReason::ControlMessage,
+            2 => KillReason::Signal,
+            3 => KillReason::FileFlag,
+            4 => KillReason::PnlBreach,
+            5 => KillReason::Manual,
+            6 => KillReason::Shutdown,
+            _ => return None,
+        };
+        Some(reason)
+    }
+}
+
+/// Cheap clonable handle that pulls the kill switch , the first reason wins.
+#[derive(Debug, Clone, Default)]
+pub struct KillTrigger {
+    reason: Arc<AtomicU8>, // 0 = not triggered
+}
+
+impl KillTrigger {
+    /// true if this call was the one that pulled it
+    pub fn trigger(&self, reason: KillReason) -> bool {
+        self.reason
+            .compare_exchange(0, reason as u8, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    pub fn reason(&self) -> Option<KillReason> {
+        KillReason::from_u8(self.reason.load(Ordering::SeqCst))
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.reason.load(Ordering::Relaxed) != 0
+    }
+}
+
+/// Kill switch state as the market maker sees it.
+#[derive(Debug, Default)]
+pub struct KillSwitch {
+    pub trigger: KillTrigger,
+    // cancels for every symbol have been queued
+    pub engaged: bool,
+    // every cancel ack came back , nothing of ours rests on the engine
+    pub completed: bool,
+    // kill control messages , acked once it completes
+    pub requests: Vec<MessageFromApi>,
+    // pulled when this file shows up
+    pub flag_file: Option<PathBuf>,
+    pub last_flag_check: u64, // clock nanos
+}
+
+impl KillSwitch {
+    pub fn new(flag_file: Option<PathBuf>) -> Self {
+        Self {
+            flag_file,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.trigger.is_triggered()
+    }
+
+    /// a handle for other threads , the same switch
+    pub fn handle(&self) -> KillTrigger {
+        self.trigger.clone()
+    }
+
+    pub fn flag_file_present(&self) -> bool {
+        self.flag_file.as_deref().is_some_and(Path::exists)
+    }
+}
@@ -1,21 +1,24 @@
 use market_maker_rs::{Decimal, dec, market_state::volatility::VolatilityEstimator, 
     prelude::{InventoryPosition, MarketState, PnL}, strategy::{avellaneda_stoikov::calculate_optimal_quotes}};
 use rustc_hash::FxHashMap;
-use std::{collections::VecDeque, time::{Instant}};
-use crate::{mmbot::{constants::{
-    BOOTSTRAP_LEVELS, BOOTSTRAP_SPREAD_PCT, CAPPED_LEVELS, MAX_DISTANCE_IN_TICKS_TO_CANCEL_BOOTSTRAP, MAX_DISTANCE_IN_TICKS_TO_CANCEL_NORMAL, MAX_DISTANCE_IN_TICKS_TO_CANCEL_STRESSED, MIN_PROFITABLE_SPREAD_IN_TICKS_BOOTSTRAP, MIN_PROFITABLE_SPREAD_IN_TICKS_NORMAL, MIN_PROFITABLE_SPREAD_IN_TICKS_STRESSED, MIN_SAMPLES_TO_EXIT_BOOTSTRAP, MIN_TRADES_TO_EXIT_BOOTSTRAP, MIN_VOLUME_TO_EXIT_BOOTSTRAP, NORMAL_LEVELS, NORMAL_SIZE_DECAY, STRESSED_LEVELS, STRESSED_SPREAD_MULT}, rolling_price::RollingPrice, 
-    types::{CancelData, InventorySatus, MmError, PostData, QuotingMode, SymbolOrders, TargetLadder, TargetQuotes}}, 
+use std::collections::VecDeque;
+use crate::{mmbot::{config::{AckTimeouts, MmConfig, StrategyParam, SymbolConfig, PARAM_VALUE_SCALE}, rolling_price::RollingPrice, 
+    clock::{elapsed, Clock, MonotonicClock},
+    types::{CancelData, Ghost, InventorySatus, MmError, ModeChange, PostData, QuotingMode, RejectReason, ReplaceData, RequoteOrders, SequenceTracker, SymbolOrders, TargetLadder, TargetQuotes, TickSummary}}, 
     shm::{feed_queue_mm::{MarketMakerFeed, MarketMakerFeedQueue}, 
     fill_queue_mm::{MarketMakerFill, MarketMakerFillQueue}, 
     order_queue_mm::{MarketMakerOrderQueue, MmOrder, QueueError}, 
-    response_queue_mm::{MessageFromApi, MessageFromApiQueue}}};
+    response_queue_mm::{MessageFromApi, MessageFromApiQueue}, 
+    wait::{Backoff, WaitStrategy}},
+    transport::{ApiSource, FeedSource, FillSource, OrderSink}};
 use rust_decimal::prelude::ToPrimitive;
 use crate::mmbot::types::{OrderState  , Side , PendingOrder};
-use crate::mmbot::constants::{SAMPLE_GAP , MAX_SYMBOLS , VOLITILTY_CALC_GAP , 
-    QUOTING_GAP , MANAGEMENT_CYCLE_GAP , TARGET_INVENTORY , MAX_SIZE_FOR_ORDER , INVENTORY_CAP , MAX_BOOK_MULT , 
-    TICK_SIZE  , MIN_PROFITABLE_SPREAD_IN_TICKS , INVENTORY_CANCELLATION_TRIGGER_AMNT ,
-    MAX_ORDER_AGE , MAX_ALLOWED_NEG_TOTAL_PNL , MAX_ALLOWED_NEG_REALISED_PNL , BASE_SIZE_BOOTSTRAP
-}; 
+use crate::mmbot::constants::{MAX_SYMBOLS , DRAIN_BATCH_SIZE , QUEUE_HEARTBEAT_GAP , KILL_FILE_CHECK_GAP}; 
+use crate::mmbot::kill_switch::{KillReason, KillSwitch, KillTrigger};
+use crate::mmbot::signals;
+use crate::mmbot::client_id::{ClientId, MAX_CLIENT_ID_SYMBOL};
+use crate::mmbot::reconcile::{reconcile, Discrepancy};
+use crate::mmbot::shutdown::{ShutdownReport, SymbolReport};
 
 
 
@@ -50,9 +53,9 @@ pub struct SymbolState{
 
     // Timing
    // pub last_quoted: Instant, // last quoting for this symbol 
-    pub last_volatility_calc: Instant, // last volatility calculation
-    pub last_sample_time: Instant, // when did we add the mid price to the rolling prices array last 
-    pub last_management_cycle_time : Instant,
+    pub last_volatility_calc: u64, // last volatility calculation
+    pub last_sample_time: u64, // when did we add the mid price to the rolling prices array last 
+    pub last_management_cycle_time : u64, // all of these are clock nanos
   
     // AS model constants 
     pub risk_aversion: Decimal,       
@@ -69,16 +72,29 @@ pub struct SymbolState{
     pub total_volume: u64,
     pub is_bootstrapped: bool,
 
-
+    // engine stream sequencing 
+    pub feed_seq : SequenceTracker,
+    pub fill_seq : SequenceTracker,
+    // a fill went missing , inventory and pnl can no longer be trusted 
+    // cleared by a clean reconcile whose query went out after the gap
+    pub fill_gap_detected : bool,
+    pub fill_gap_query : u64, // last reconcile request id when the gap was seen
+    // the kill switch was pulled , stays in emergency for good
+    pub halted : bool,
+    // acks went missing , no quoting until every stuck order is resolved or forgotten
+    pub quarantined : bool,
 
 
     pub current_mode : QuotingMode,
     pub prev_mode: QuotingMode,
+
+    // quoting and risk parameters , copied in when the symbol is added 
+    pub config : SymbolConfig,
     
 }
 // each symbol state shud have a defualt inventory for init (ik)
 impl SymbolState{
-    pub fn new(ipo_price : Decimal , symbol:u32)->Self{
+    pub fn new(ipo_price : Decimal , symbol:u32 , now : u64 , config : SymbolConfig)->Self{
         Self { 
             symbol ,
             ipo_price , 
@@ -95,17 +111,24 @@ impl SymbolState{
             rolling_prices : RollingPrice { deque: VecDeque::with_capacity(100), capacity: 100 } ,
             inventory : InventoryPosition::new() ,
             pnl : PnL::new(),
-            last_sample_time : Instant::now() ,
-            last_volatility_calc : Instant::now() ,
-            last_management_cycle_time : Instant::now(),
-            risk_aversion :dec!(0) , // decide ,,
-            time_to_terminal : 0 , // decide 
-            liquidity_k : dec!(0) , // decide , 
+            last_sample_time : now ,
+            last_volatility_calc : now ,
+            last_management_cycle_time : now,
+            risk_aversion : config.risk_aversion ,
+            time_to_terminal : config.time_to_terminal ,
+            liquidity_k : config.liquidity_k , 
             total_trades : 0 , 
             total_volume : 0 , 
             is_bootstrapped : false , 
+            feed_seq : SequenceTracker::default(),
+            fill_seq : SequenceTracker::default(),
+            fill_gap_detected : false,
+            fill_gap_query : 0,
+            halted : false,
+            quarantined : false,
             current_mode : QuotingMode::Bootstrap  ,
             prev_mode : QuotingMode::Bootstrap  ,
+            config ,
 
         }
         // find the sollutiton for the best bid and the best ask value at cold start 
@@ -115,26 +138,26 @@ impl SymbolState{
         &self,
     ) -> (u64, u64) {
        
-        if INVENTORY_CAP <= dec!(0) || MAX_SIZE_FOR_ORDER == dec!(0) {
+        if self.config.inventory_cap <= dec!(0) || self.config.max_size_for_order == dec!(0) {
             return (0, 0);
         }
 
         let inv = self.inventory.quantity;
-        let dev = inv - TARGET_INVENTORY; 
+        let dev = inv - self.config.target_inventory; 
         let abs_dev = dev.abs();
 
         
         let vol = self.market_state.volatility.max(dec!(0));
         let vol_factor = dec!(1) / (dec!(1) + vol); // in (0,1]
 
-        let inv_ratio = (abs_dev / INVENTORY_CAP).min(dec!(1));
+        let inv_ratio = (abs_dev / self.config.inventory_cap).min(dec!(1));
 
         
         let inv_ratio_f = inv_ratio.to_f64().unwrap_or(1.0);
         let vol_factor_f = vol_factor.to_f64().unwrap_or(0.1);
 
        
-        let mut base = (MAX_SIZE_FOR_ORDER.to_f64().unwrap_or(50.0) * vol_factor_f).round() as i64;
+        let mut base = (self.config.max_size_for_order.to_f64().unwrap_or(50.0) * vol_factor_f).round() as i64;
         base = base.max(1);
 
         
@@ -152,7 +175,7 @@ impl SymbolState{
         };
 
         
-        if abs_dev >= INVENTORY_CAP {
+        if abs_dev >= self.config.inventory_cap {
           
             if dev > dec!(0) {
               
@@ -160,19 +183,17 @@ impl SymbolState{
             } else if dev < dec!(0) {
               
                 ask_size = 0;
-            } else {
-               
-            }
+            } 
         }
 
       
-        let max_size_i64 = MAX_SIZE_FOR_ORDER.to_i64().unwrap_or(50);
+        let max_size_i64 = self.config.max_size_for_order.to_i64().unwrap_or(50);
         bid_size = bid_size.clamp(0, max_size_i64);
         ask_size = ask_size.clamp(0, max_size_i64);
 
         let best_bid_qty = self.best_bid_qty as u64;
         let best_ask_qty = self.best_ask_qty as u64;
-        let max_book_mult_u64 = MAX_BOOK_MULT.to_u64().unwrap_or(2);
+        let max_book_mult_u64 = self.config.max_book_mult.to_u64().unwrap_or(2);
 
         if best_bid_qty > 0 {
             let cap = best_bid_qty.saturating_mul(max_book_mult_u64).max(1);
@@ -194,8 +215,8 @@ impl SymbolState{
 
     pub fn should_exit_bootstrap(&mut self)->bool{
         // all of the conditions need to be met before the bootstrap mode can get finished 
-        let min_trades = self.total_trades >= MIN_TRADES_TO_EXIT_BOOTSTRAP;
-        let min_volume = self.total_volume >= MIN_VOLUME_TO_EXIT_BOOTSTRAP;
+        let min_trades = self.total_trades >= self.config.min_trades_to_exit_bootstrap;
+        let min_volume = self.total_volume >= self.config.min_volume_to_exit_bootstrap;
         
         //not enough activity 
         if !min_trades || !min_volume {
@@ -203,21 +224,21 @@ impl SymbolState{
         }
 
         //not enough data for volatility calc
-        let enough_samples = self.rolling_prices.len() >= MIN_SAMPLES_TO_EXIT_BOOTSTRAP;
+        let enough_samples = self.rolling_prices.len() >= self.config.min_samples_to_exit_bootstrap;
         if !enough_samples {
             return false;  // Can't calculate volatility yet
         }
 
         // greater spread pct than 2 -> non volatile market 
         let current_spread = self.best_ask - self.best_bid;
-        let spread_in_ticks = current_spread/TICK_SIZE;
+        let spread_in_ticks = current_spread/self.config.tick_size;
         //let spread_pct = if self.market_state.mid_price > dec!(0) {
         //    (current_spread / self.market_state.mid_price).to_f64().unwrap_or(1.0)
         //} else {
         //    1.0
         //};
 
-        let spread_tight = spread_in_ticks < dec!(7);  
+        let spread_tight = spread_in_ticks < self.config.max_spread_in_ticks_to_exit_bootstrap;  
         
         if !spread_tight {
             return false;  
@@ -236,10 +257,9 @@ impl SymbolState{
 
 
     pub fn determine_mode(&mut self)->QuotingMode{
-        // emergency mode check due to losses 
-        if self.pnl.total < MAX_ALLOWED_NEG_TOTAL_PNL || self.pnl.realized < MAX_ALLOWED_NEG_REALISED_PNL {
-            // Cancel ALL active orders
-            //self.cancel_all_orders(symbol);
+        // emergency mode check due to losses , because a missed fill left the inventory unknown , or the kill switch 
+        if self.halted || self.pnl.total < self.config.max_allowed_neg_total_pnl || self.pnl.realized < self.config.max_allowed_neg_realised_pnl || self.fill_gap_detected {
+            // every resting order gets cancelled when tick sees the switch into this mode 
             self.prev_mode = self.current_mode;
             self.current_mode = QuotingMode::Emergency;
             return QuotingMode::Emergency;
@@ -249,15 +269,13 @@ impl SymbolState{
 
         // inventory cap mode check 
         let inv_abs = self.inventory.quantity.abs();
-        let inv_ratio = (inv_abs / INVENTORY_CAP).to_f64().unwrap_or(0.0);
-        if inv_abs >= INVENTORY_CAP {  // Hard cap hit
+        let inv_ratio = (inv_abs / self.config.inventory_cap).to_f64().unwrap_or(0.0);
+        if inv_abs >= self.config.inventory_cap {  // Hard cap hit
             let side = if self.inventory.quantity > dec!(0) {
-                // Cancel all BUY orders (don't buy more)
-                //self.cancel_side(symbol, 0);
+                // the BUY orders get cancelled when tick sees the switch (don't buy more)
                 InventorySatus::Long
             } else {
-                // Cancel all SELL orders (don't sell more)
-                //self.cancel_side(symbol, 1);
+                // the SELL orders get cancelled when tick sees the switch (don't sell more)
                 InventorySatus::Short
             };
             self.prev_mode = self.current_mode;
@@ -282,7 +300,7 @@ impl SymbolState{
 
         // stressed more in terms of high volatility 
         let _vol_pct = (self.market_state.volatility * dec!(100)).to_f64().unwrap_or(0.0);
-        let is_high_volatility = self.market_state.volatility > dec!(0.06);  // 6%
+        let is_high_volatility = self.market_state.volatility > self.config.stressed_volatility;
         let is_inventory_warning = inv_ratio >= 0.80;  // 80% of cap
         
         if is_high_volatility || is_inventory_warning {
@@ -296,12 +314,12 @@ impl SymbolState{
         self.current_mode = QuotingMode::Normal ;
         
         // default
-       return  QuotingMode::Normal;
+       QuotingMode::Normal
     }
 
     pub fn should_cancel_unprofitable_order(&self , order : &PendingOrder , current_mid : Decimal , current_spread:Decimal)->bool{
         let distance_from_mid = (order.price - current_mid).abs();
-        let distance_from_mid_in_ticks = distance_from_mid/TICK_SIZE;
+        let distance_from_mid_in_ticks = distance_from_mid/self.config.tick_size;
 
         // bestbid(highest buying price ) < midprice < best ask(lowest selling price )
 
@@ -314,40 +332,40 @@ impl SymbolState{
         }
         
 
-        let current_spread_in_ticks = current_spread / TICK_SIZE;
+        let current_spread_in_ticks = current_spread / self.config.tick_size;
 
         match self.current_mode{
-            QuotingMode::Bootstrap{..}=>{
-                if distance_from_mid_in_ticks > MAX_DISTANCE_IN_TICKS_TO_CANCEL_BOOTSTRAP {
+            QuotingMode::Bootstrap=>{
+                if distance_from_mid_in_ticks > self.config.bootstrap.max_distance_in_ticks {
                     // 20 ticks is 5 rs or 5 dollars  
                     return true;  
                 }
                 
-                if current_spread_in_ticks < MIN_PROFITABLE_SPREAD_IN_TICKS_BOOTSTRAP {
+                if current_spread_in_ticks < self.config.bootstrap.min_profitable_spread_in_ticks {
                     // 8 ticks => 2rs 
                     return true;  
                 }
             }
 
-            QuotingMode::Normal{..}=>{
-                if distance_from_mid_in_ticks > MAX_DISTANCE_IN_TICKS_TO_CANCEL_NORMAL{
+            QuotingMode::Normal=>{
+                if distance_from_mid_in_ticks > self.config.normal.max_distance_in_ticks{
                     // 2.5 rs , max mid movement allowed 
                     return true;  
                 }
                 
-                if current_spread_in_ticks < MIN_PROFITABLE_SPREAD_IN_TICKS_NORMAL {
+                if current_spread_in_ticks < self.config.normal.min_profitable_spread_in_ticks {
                     // 0.5 rs min spread to ensure prpfit 
                     return true;  
                 }
             }
 
-            QuotingMode::Stressed{..}=>{
-                if distance_from_mid_in_ticks > MAX_DISTANCE_IN_TICKS_TO_CANCEL_STRESSED {
+            QuotingMode::Stressed=>{
+                if distance_from_mid_in_ticks > self.config.stressed.max_distance_in_ticks {
                     // 1.75 rs , max mid movement allowed
                     return true;  
                 }
                 
-                if current_spread_in_ticks < MIN_PROFITABLE_SPREAD_IN_TICKS_STRESSED {
+                if current_spread_in_ticks < self.config.stressed.min_profitable_spread_in_ticks {
                     // 1.25 -> min possible spread for profit 
                     return true;  
                 }
@@ -369,9 +387,9 @@ impl SymbolState{
         order: &PendingOrder,
         inventory: Decimal,
     ) -> bool {
-        let inv_ratio = inventory.abs() / INVENTORY_CAP;
+        let inv_ratio = inventory.abs() / self.config.inventory_cap;
 
-        if inv_ratio >= INVENTORY_CANCELLATION_TRIGGER_AMNT {
+        if inv_ratio >= self.config.inventory_cancellation_trigger {
             // dont buy more , cancel them 
             if inventory > dec!(0) && order.side == Side::BID {
                 return true;  
@@ -388,39 +406,189 @@ impl SymbolState{
 
 pub struct SymbolContext{
     pub state :  SymbolState , 
-    pub orders : SymbolOrders
+    pub orders : SymbolOrders ,
+    // parameter updates from the api , swapped in at the start of the next management cycle
+    pub staged_config : Option<SymbolConfig>
 }
 
 
 impl SymbolContext{
-    pub fn new(ipo_price : Decimal , symbol : u32)->Self{
+    pub fn new(ipo_price : Decimal , symbol : u32 , now : u64 , config : SymbolConfig , epoch : u16)->Self{
         Self{
-            state : SymbolState::new(ipo_price, symbol) , 
-            orders : SymbolOrders::new(symbol)
+            state : SymbolState::new(ipo_price, symbol, now, config) , 
+            orders : SymbolOrders::new(symbol, now, epoch) ,
+            staged_config : None
+        }
+    }
+
+    // validates the change against whatever is already staged , so several updates in one cycle add up
+    pub fn stage_param(&mut self , param : StrategyParam , value : Decimal)->Result<() , MmError>{
+        let base = self.staged_config.unwrap_or(self.state.config);
+        let config = base.with_param(param, value).map_err(MmError::InvalidParam)?;
+        self.staged_config = Some(config);
+        Ok(())
+    }
+
+    // cancels every resting order , or only one side of the book 
+    // orders still waiting for their accept ack are flagged and cancelled as soon as the ack arrives
+    // returns how many cancels went into the batch
+    pub fn cancel_orders(&mut self , symbol : u32 , side : Option<Side> , cancel_batch : &mut Vec<CancelData> , now : u64)->usize{
+        let mut cancels = 0;
+        self.orders.for_each_mut(|order| {
+            if side.is_some_and(|side| side != order.side) {
+                return;
+            }
+            match order.state {
+                OrderState::Active | OrderState::PartiallyFilled => {
+                    if let Some(order_id) = order.exchange_order_id {
+                        cancel_batch.push(CancelData { symbol , client_id: order.client_id, order_id: Some(order_id) });
+                        order.mark_pending_cancel(now);
+                        cancels += 1;
+                    }
+                }
+                OrderState::PendingNew | OrderState::PendingReplace => {
+                    order.cancel_on_ack = true;
+                }
+                OrderState::PendingCancel | OrderState::CompletelyFilled => {}
+            }
+        });
+        // ghosts are not on a side we know of any more , a full cancel covers them
+        // one already cancelled is left to check_ack_timeouts , it resends once the cancel timed out
+        if side.is_none() {
+            for ghost in &mut self.orders.ghosts {
+                if let Some(order_id) = ghost.order_id && ghost.cancel_sent_at.is_none() {
+                    cancel_batch.push(CancelData { symbol , client_id: ghost.client_id, order_id: Some(order_id) });
+                    ghost.cancel_sent_at = Some(now);
+                    cancels += 1;
+                }
+            }
         }
+        cancels
+    }
+
+    pub fn apply_staged_config(&mut self)->bool{
+        let Some(config) = self.staged_config.take() else {
+            return false;
+        };
+        // the AS inputs live on the state as well 
+        self.state.risk_aversion = config.risk_aversion;
+        self.state.liquidity_k = config.liquidity_k;
+        self.state.time_to_terminal = config.time_to_terminal;
+        self.state.config = config;
+        true
     }
 
     pub fn check_if_time_caused_cancellation( &mut self,
-        symbol: u32, cancel_batch: &mut Vec<CancelData>, ){
+        symbol: u32, cancel_batch: &mut Vec<CancelData>, now : u64){
         
 
-        for order in &mut self.orders.pending_orders {
+        let max_order_age = self.state.config.max_order_age;
+        self.orders.for_each_mut(|order| {
             if order.state != OrderState::Active {
-                continue;
+                return;
+            }
+
+            let age = elapsed(now, order.created_at);
+            if age > max_order_age
+                && let Some(order_id) = order.exchange_order_id {
+                // sen directly to the order cancell queue , expose a function 
+                cancel_batch.push(CancelData { symbol  , client_id: order.client_id, order_id: Some(order_id) });
+                order.mark_pending_cancel(now);
             }
+        });
+    }
 
-            let age = order.created_at.elapsed();
-            if age > MAX_ORDER_AGE {
-                if let Some(order_id) = order.exchange_order_id {
-                    // sen directly to the order cancell queue , expose a function 
-                    cancel_batch.push(CancelData { symbol  , client_id: order.client_id, order_id: Some(order_id) });
-                    order.state = OrderState::PendingCancel;
+    // posts and amends that never got an accept or a reject and cancels that never got their ack
+    // an unanswered amend still rests under its exchange id and is cancelled with it
+    // unanswered cancels are resent up to max_cancel_retries times , after that (or for a silent post) the
+    // order is stuck : the symbol is quarantined and cancelled out , the order leaves the book once it has been
+    // stuck for ghost_expiry but is kept as a ghost until the engine shows it is gone ,
+    // the quarantine lifts when no stuck order is left
+    // returns how many timeouts this pass hit
+    pub fn check_ack_timeouts(&mut self , symbol : u32 , cancel_batch : &mut Vec<CancelData> , now : u64 , timeouts : &AckTimeouts)->usize{
+        let mut stats = self.orders.ack_timeouts;
+        let mut timed_out = 0;
+        let mut stuck = false;
+        self.orders.for_each_mut(|order| {
+            if order.stuck_since.is_some() {
+                return;
+            }
+            match order.state {
+                OrderState::PendingReplace if elapsed(now, order.created_at) >= timeouts.new
+                    && let Some(order_id) = order.exchange_order_id => {
+                    stats.new_ack_timeouts += 1;
+                    timed_out += 1;
+                    cancel_batch.push(CancelData { symbol , client_id: order.client_id, order_id: Some(order_id) });
+                    order.mark_pending_cancel(now);
+                    // a late replace ack may move it to a new exchange id , that one is cancelled as well
+                    order.cancel_on_ack = true;
+                    stuck = true;
                 }
+                OrderState::PendingNew | OrderState::PendingReplace if elapsed(now, order.created_at) >= timeouts.new => {
+                    stats.new_ack_timeouts += 1;
+                    timed_out += 1;
+                    // if the accept does show up late it is cancelled right away
+                    order.cancel_on_ack = true;
+                    order.stuck_since = Some(now);
+                    stuck = true;
+                }
+                OrderState::PendingCancel if elapsed(now, order.cancel_sent_at) >= timeouts.cancel => {
+                    stats.cancel_ack_timeouts += 1;
+                    timed_out += 1;
+                    if order.cancel_retries < timeouts.max_cancel_retries
+                        && let Some(order_id) = order.exchange_order_id {
+                        cancel_batch.push(CancelData { symbol , client_id: order.client_id, order_id: Some(order_id) });
+                        order.cancel_sent_at = now;
+                        order.cancel_retries += 1;
+                        stats.cancel_retries += 1;
+                    } else {
+                        order.stuck_since = Some(now);
+                        stuck = true;
+                    }
+                }
+                _ => {}
+            }
+        });
+        self.orders.ack_timeouts = stats;
+
+        let mut ghosts = std::mem::take(&mut self.orders.ghosts);
+        let before = ghosts.len();
+        self.orders.retain(|order| {
+            if order.stuck_since.is_some_and(|since| elapsed(now, since) >= timeouts.ghost_expiry) {
+                let cancel_sent_at = (order.state == OrderState::PendingCancel).then_some(order.cancel_sent_at);
+                ghosts.push(Ghost { client_id : order.client_id , order_id : order.exchange_order_id , dropped_at : now , cancel_sent_at });
+                return false;
             }
+            true
+        });
+        // a ghost's cancel is resent for as long as the engine may still have it , at most once per cancel timeout
+        for ghost in &mut ghosts {
+            if let (Some(order_id) , Some(sent_at)) = (ghost.order_id , ghost.cancel_sent_at)
+                && elapsed(now, sent_at) >= timeouts.cancel {
+                cancel_batch.push(CancelData { symbol , client_id: ghost.client_id, order_id: Some(order_id) });
+                ghost.cancel_sent_at = Some(now);
+            }
+        }
+        let dropped = ghosts.len() - before;
+        self.orders.ghosts = ghosts;
+        if dropped > 0 {
+            self.orders.ack_timeouts.ghosts_dropped += dropped as u64;
+            eprintln!(" symbol {} gave up on {} orders that never got their ack , kept as ghosts" , symbol , dropped);
+        }
+
+        if stuck && !self.state.quarantined {
+            self.state.quarantined = true;
+            self.orders.ack_timeouts.quarantines += 1;
+            eprintln!(" symbol {} quarantined , acks went missing" , symbol);
+            self.cancel_orders(symbol, None, cancel_batch, now);
+        } else if self.state.quarantined && self.orders.iter().all(|order| order.stuck_since.is_none()) {
+            self.state.quarantined = false;
+            eprintln!(" symbol {} out of quarantine" , symbol);
         }
+        timed_out
     }
 
-    pub fn should_requote(&self) -> bool {
+    pub fn should_requote(&self , now : u64) -> bool {
 
 
         // dont quote again in emergency mode 
@@ -429,8 +597,14 @@ impl SymbolContext{
         }
         
 
+        // the engine kept rejecting us for the same reason , give it a rest 
+        // or we lost track of what rests on the book 
+        if self.orders.rejects.in_backoff(now) || self.state.quarantined {
+            return false;
+        }
+
         // not enough time passed 
-        if self.orders.last_quote_time.elapsed() < QUOTING_GAP {
+        if elapsed(now, self.orders.last_quote_time) < self.state.config.quoting_gap {
             return false;
         }
 
@@ -439,14 +613,10 @@ impl SymbolContext{
             return true;
         }
         
-        // getting active orders
-        let active_bids = self.orders.pending_orders.iter()
-            .filter(|o| o.side == Side::BID && matches!(o.state, OrderState::Active))
-            .count();
+        // getting active orders , an amend in flight still rests on the book
+        let active_bids = self.orders.count(Side::BID, OrderState::Active) + self.orders.count(Side::BID, OrderState::PendingReplace);
         
-        let active_asks = self.orders.pending_orders.iter()
-            .filter(|o| o.side == Side::ASK && matches!(o.state, OrderState::Active))
-            .count();
+        let active_asks = self.orders.count(Side::ASK, OrderState::Active) + self.orders.count(Side::ASK, OrderState::PendingReplace);
         
         let total_active = active_bids + active_asks;
         
@@ -459,14 +629,14 @@ impl SymbolContext{
         match self.state.current_mode {
             QuotingMode::Bootstrap  => {
                 // these willl be constants for now 
-                let expected = BOOTSTRAP_LEVELS * 2;
+                let expected = self.state.config.bootstrap_levels * 2;
                 if total_active < expected / 2 {
                     return true;
                 }
             }
             
             QuotingMode::Normal => {
-                let expected = NORMAL_LEVELS * 2;
+                let expected = self.state.config.normal_levels * 2;
                 // Missing one side
                 if active_bids == 0 || active_asks == 0 {
                     return true;
@@ -478,14 +648,14 @@ impl SymbolContext{
             }
             
             QuotingMode::Stressed  => {
-                let expected = STRESSED_LEVELS * 2;
+                let expected = self.state.config.stressed_levels * 2;
                 if total_active < expected / 2 {
                     return true;
                 }
             }
             
             QuotingMode::InventoryCapped { side } => {
-                let expected = CAPPED_LEVELS;
+                let expected = self.state.config.capped_levels;
                 let active_on_required_side = match side {
                     InventorySatus::Long => active_asks,   // Need asks to sell
                     InventorySatus::Short => active_bids,  // Need bids to buy
@@ -503,7 +673,7 @@ impl SymbolContext{
 
 
         let mid_move = (self.state.market_state.mid_price - self.state.prev_mid_price).abs();
-        let mid_move_ticks = mid_move/TICK_SIZE;
+        let mid_move_ticks = mid_move/self.state.config.tick_size;
         //let mid_move_pct = if self.state.prev_mid_price != dec!(0) {
         //    (mid_move / self.state.prev_mid_price).to_f64().unwrap_or(0.0)
         //} else {
@@ -551,20 +721,20 @@ impl SymbolContext{
 
     pub fn build_bootstrap_ladder(&self)->Result<TargetLadder , MmError>{
         
-        let half_spread = self.state.ipo_price * BOOTSTRAP_SPREAD_PCT / dec!(2);
+        let half_spread = self.state.ipo_price * self.state.config.bootstrap_spread_pct / dec!(2);
         let center_bid = self.state.ipo_price - half_spread;
         let center_ask = self.state.ipo_price + half_spread;
         
-        let mut bids = Vec::with_capacity(BOOTSTRAP_LEVELS);
-        let mut asks = Vec::with_capacity(BOOTSTRAP_LEVELS);
+        let mut bids = Vec::with_capacity(self.state.config.bootstrap_levels);
+        let mut asks = Vec::with_capacity(self.state.config.bootstrap_levels);
         
-        for i in 0..BOOTSTRAP_LEVELS {
-            let offset = TICK_SIZE * Decimal::from(i);
-            let size = (BASE_SIZE_BOOTSTRAP as f64 * 0.85_f64.powi(i as i32)) as u64;
+        for i in 0..self.state.config.bootstrap_levels {
+            let offset = self.state.config.tick_size * Decimal::from(i);
+            let size = (self.state.config.base_size_bootstrap as f64 * self.state.config.bootstrap_size_decay.powi(i as i32)) as u64;
             
             bids.push(TargetQuotes {
                 price: center_bid - offset,
-                qty: size.max(10) as u32, // configure quanjtity 
+                qty: self.state.config.order_qty(size), // configure quanjtity 
                 side: Side::BID,
                 level : i 
                 
@@ -572,7 +742,7 @@ impl SymbolContext{
             
             asks.push(TargetQuotes {
                 price: center_ask + offset,
-                qty: size.max(10) as u32,
+                qty: self.state.config.order_qty(size),
                 side: Side::ASK,
                 level : i 
             });
@@ -594,18 +764,18 @@ impl SymbolContext{
                 // optimal bid price and the optimal ask price 
                 let (bid_size , ask_size) = self.state.compute_quote_sizes();
 
-                let mut bids = Vec::with_capacity(NORMAL_LEVELS);
-                let mut asks = Vec::with_capacity(NORMAL_LEVELS);
+                let mut bids = Vec::with_capacity(self.state.config.normal_levels);
+                let mut asks = Vec::with_capacity(self.state.config.normal_levels);
 
 
-                for i in 0..NORMAL_LEVELS {
-                    let offset = TICK_SIZE * Decimal::from(i);
-                    let bid_size = (bid_size as f64 * NORMAL_SIZE_DECAY.powi(i as i32)) as u64;
-                    let ask_size = (ask_size as f64 * NORMAL_SIZE_DECAY.powi(i as i32)) as u64;
+                for i in 0..self.state.config.normal_levels {
+                    let offset = self.state.config.tick_size * Decimal::from(i);
+                    let bid_size = (bid_size as f64 * self.state.config.normal_size_decay.powi(i as i32)) as u64;
+                    let ask_size = (ask_size as f64 * self.state.config.normal_size_decay.powi(i as i32)) as u64;
 
                     bids.push(TargetQuotes {
                         price: quotes.0 - offset,
-                        qty: bid_size.max(10) as u32,
+                        qty: self.state.config.order_qty(bid_size),
                         side: Side::BID,
                         level : i 
 
@@ -613,7 +783,7 @@ impl SymbolContext{
 
                     asks.push(TargetQuotes {
                         price: quotes.1 + offset,
-                        qty: ask_size.max(10) as u32,
+                        qty: self.state.config.order_qty(ask_size),
                         side: Side::ASK,
                         level : i
                     });
@@ -622,15 +792,12 @@ impl SymbolContext{
                 Ok(TargetLadder { bids, asks })
             }
             Err(_)=>{
-                return Err(MmError::CouldNotCalculateQuotes);
+                Err(MmError::CouldNotCalculateQuotes)
             }
         }
     }
 
     pub fn build_stressed_ladder(&self)->Result<TargetLadder , MmError>{
-        const TICK_SIZE: Decimal = dec!(0.25);
-        const BASE_SIZE: u64 = 50;
-
         match calculate_optimal_quotes(
             self.state.market_state.mid_price, 
             self.state.inventory.quantity, 
@@ -644,21 +811,21 @@ impl SymbolContext{
                 let center_ask = quotes.1;
 
                 let current_spread = center_ask - center_bid;
-                let extra_spread = current_spread * (STRESSED_SPREAD_MULT - dec!(1)) / dec!(2);
+                let extra_spread = current_spread * (self.state.config.stressed_spread_mult - dec!(1)) / dec!(2);
                 
                 let new_bid = center_bid - extra_spread;
                 let new_ask = center_ask + extra_spread;
                 
-                let mut bids = Vec::with_capacity(STRESSED_LEVELS);
-                let mut asks = Vec::with_capacity(STRESSED_LEVELS);
+                let mut bids = Vec::with_capacity(self.state.config.stressed_levels);
+                let mut asks = Vec::with_capacity(self.state.config.stressed_levels);
                 
-                for i in 0..STRESSED_LEVELS {
-                    let offset = TICK_SIZE * Decimal::from(i);
-                    let size = (BASE_SIZE as f64 * 0.80_f64.powi(i as i32)) as u64;
+                for i in 0..self.state.config.stressed_levels {
+                    let offset = self.state.config.tick_size * Decimal::from(i);
+                    let size = (self.state.config.base_size_stressed as f64 * self.state.config.stressed_size_decay.powi(i as i32)) as u64;
                     
                     bids.push(TargetQuotes {
                         price: new_bid - offset,
-                        qty: size.max(10) as u32,
+                        qty: self.state.config.order_qty(size),
                         side: Side::BID,
                         level : i 
                     });
@@ -666,7 +833,7 @@ impl SymbolContext{
                     
                     asks.push(TargetQuotes {
                         price: new_ask + offset,
-                        qty: size.max(10) as u32,
+                        qty: self.state.config.order_qty(size),
                         side: Side::ASK,
                         level : i 
                     });
@@ -675,14 +842,12 @@ impl SymbolContext{
                 Ok(TargetLadder { bids, asks })
             }
             Err(_)=>{
-                return Err(MmError::CouldNotCalculateQuotes);
+                Err(MmError::CouldNotCalculateQuotes)
             }
         }
     }
 
     pub fn build_capped_ladder(&self , side : InventorySatus)->Result<TargetLadder , MmError>{
-        const TICK_SIZE: Decimal = dec!(0.25);
-        const BASE_SIZE: u64 = 150; 
         match calculate_optimal_quotes(
             self.state.market_state.mid_price, 
             self.state.inventory.quantity, 
@@ -702,13 +867,13 @@ impl SymbolContext{
                 match side {
                     InventorySatus::Long => {
                         // Only asks (to sell)
-                        for i in 0..CAPPED_LEVELS {
-                            let offset = TICK_SIZE * Decimal::from(i);
-                            let size = (BASE_SIZE as f64 * 0.90_f64.powi(i as i32)) as u64;
+                        for i in 0..self.state.config.capped_levels {
+                            let offset = self.state.config.tick_size * Decimal::from(i);
+                            let size = (self.state.config.base_size_capped as f64 * self.state.config.capped_size_decay.powi(i as i32)) as u64;
                             
                             asks.push(TargetQuotes {
                                 price: center_ask + offset,
-                                qty: size.max(10) as u32,
+                                qty: self.state.config.order_qty(size),
                                 side: Side::ASK,
                                 level :  i 
                             });
@@ -717,13 +882,13 @@ impl SymbolContext{
                     
                     InventorySatus::Short => {
                         // Only bids (to buy)
-                        for i in 0..CAPPED_LEVELS {
-                            let offset = TICK_SIZE * Decimal::from(i);
-                            let size = (BASE_SIZE as f64 * 0.90_f64.powi(i as i32)) as u64;
+                        for i in 0..self.state.config.capped_levels {
+                            let offset = self.state.config.tick_size * Decimal::from(i);
+                            let size = (self.state.config.base_size_capped as f64 * self.state.config.capped_size_decay.powi(i as i32)) as u64;
                             
                             bids.push(TargetQuotes {
                                 price: center_bid - offset,
-                                qty: size.max(10) as u32,
+                                qty: self.state.config.order_qty(size),
                                 side: Side::BID,
                                 level : i 
                             });
@@ -736,61 +901,47 @@ impl SymbolContext{
             }
 
             Err(_)=>{
-                return Err(MmError::CouldNotCalculateQuotes);
+                Err(MmError::CouldNotCalculateQuotes)
             }
         }
     }
 
 
-    pub fn incremental_requote(&mut self ,  target_ladder : &mut TargetLadder , symbol : u32)->Result<(Vec<(u64 , u64)> , Vec<PostData>) , MmError>{
+    pub fn incremental_requote(&mut self ,  target_ladder : &mut TargetLadder , symbol : u32 , now : u64)->Result<RequoteOrders , MmError>{
         const PRICE_TOLERANCE: Decimal = dec!(0.1);  // 10 cent / 10 paise 
         
      //   let mut orders_to_keep = Vec::new();
         let mut order_to_cancel = Vec::new();   
         let mut order_to_post = Vec::new();
+        let mut order_to_amend = Vec::new();
 
+        // resting orders that dont match their target any more , amended or cancelled once we know what gets posted
+        let mut stale = Vec::new();
 
-
-        for order in &mut self.orders.pending_orders{
+        for order in self.orders.iter(){
             // if state other than these two , we can skip 
             if !matches!(order.state, OrderState::Active | OrderState::PartiallyFilled) {
                 continue;
             }
 
-            let should_keep = match order.side {
-                Side::BID =>{
-                    // this is a bid order , 
-                    target_ladder.bids.iter().any(
-                        |target_quote|
-                        target_quote.level == order.level && (order.price - target_quote.price).abs() <= PRICE_TOLERANCE
-                    )
-                }
-                Side::ASK=>{
-                    target_ladder.asks.iter().any(
-                        |target_quote|
-                        target_quote.level == order.level && (order.price - target_quote.price).abs() <= PRICE_TOLERANCE
-                    )
-                }
+            // ladders are built level by level , the target for a level sits at that index
+            let targets = match order.side {
+                Side::BID => &target_ladder.bids,
+                Side::ASK => &target_ladder.asks,
             };
+            let should_keep = targets.get(order.level).is_some_and(
+                |target_quote|
+                target_quote.level == order.level && (order.price - target_quote.price).abs() <= PRICE_TOLERANCE
+            );
 
-            if should_keep {
-                //orders_to_keep.push(order);
-                // if it is not in cancel we obviously are keeping it 
-            }
-            else{
-                // we send for canncelation 
-                if let Some(order_id) = order.exchange_order_id{
-                    order_to_cancel.push((order_id , order.client_id)); // push here for now can cancel in main loop
-                }
+            if !should_keep && order.exchange_order_id.is_some() {
+                stale.push(order.client_id);
             }
         }
           // identifiying the levels which are required to be posted 
-        for target_quote in &mut target_ladder.asks{
-            let already_have = self.orders.pending_orders.iter().any(
-                |current_quote|
-                target_quote.side == current_quote.side
-                 && target_quote.level == current_quote.level 
-                 && (target_quote.price-current_quote.price).abs() <= PRICE_TOLERANCE
+        for target_quote in target_ladder.asks.iter().chain(&target_ladder.bids){
+            let already_have = self.orders.at_level(target_quote.side, target_quote.level).any(
+                |current_quote| (target_quote.price-current_quote.price).abs() <= PRICE_TOLERANCE
             );
 
             if !already_have {
@@ -805,45 +956,55 @@ impl SymbolContext{
             }
         }
 
-        for target_quote in &mut target_ladder.bids{
-            let already_have = self.orders.pending_orders.iter().any(
-                |current_quote|
-                target_quote.side == current_quote.side
-                 && target_quote.level == current_quote.level 
-                 && (target_quote.price-current_quote.price).abs() <= PRICE_TOLERANCE
-            );
-
-            if !already_have {
-                // we need to post this order , either we would return all the orders to be posted , or just post from here 
-                order_to_post.push(PostData{
-                    price : target_quote.price ,
-                    qty : target_quote.qty , 
-                    side : target_quote.side , 
-                    symbol,
-                    level : target_quote.level
-                });
-            }
+        // a stale order on the same side and level as a quote we are missing is moved there with one replace ,
+        // the level never goes empty and it is one message instead of a cancel and a post
+        for client_id in stale {
+            self.orders.update(client_id, |order| {
+                let Some(order_id) = order.exchange_order_id else {
+                    return;
+                };
+                match order_to_post.iter().position(|post| post.side == order.side && post.level == order.level) {
+                    Some(position) => {
+                        let post = order_to_post.remove(position);
+                        order_to_amend.push(ReplaceData { symbol , client_id , order_id , price : post.price , qty : post.qty , side : post.side });
+                        order.mark_pending_replace(post.price, post.qty, now);
+                    }
+                    None => {
+                        // we send for canncelation 
+                        order_to_cancel.push((order_id , client_id));
+                        order.mark_pending_cancel(now);
+                    }
+                }
+            });
         }
 
-        self.orders.last_quote_time = Instant::now();
+        self.orders.last_quote_time = now;
 
-        Ok((order_to_cancel , order_to_post))
+        Ok(RequoteOrders { cancels : order_to_cancel , posts : order_to_post , amends : order_to_amend })
     }
 }
 
-pub struct MarketMaker{
+// generic over where its inputs come from and its orders go , defaults are the shared memory queues
+// tests plug in MemQueue for all four and drive the bot without an engine
+pub struct MarketMaker<
+    Fe = MarketMakerFeedQueue,
+    Fi = MarketMakerFillQueue,
+    A = MessageFromApiQueue,
+    O = MarketMakerOrderQueue,
+    C = MonotonicClock,
+>{
 
     // Data manager for all symbols 
   //  pub symbol_states: FxHashMap<u32, SymbolState>,
-    pub message_queue : MessageFromApiQueue,
-    pub fill_queue    : MarketMakerFillQueue,
-    pub feed_queue    : MarketMakerFeedQueue,
+    pub message_queue : A,
+    pub fill_queue    : Fi,
+    pub feed_queue    : Fe,
     pub volitality_estimator : VolatilityEstimator ,
 
 
 
     //ORDER MANAGER 
-    pub order_queue   : MarketMakerOrderQueue,
+    pub order_queue   : O,
   //  pub symbol_orders: FxHashMap<u32, SymbolOrders>,
 
 
@@ -853,41 +1014,109 @@ pub struct MarketMaker{
 
 
     pub cancel_batch : Vec<CancelData>,
-    pub post_bacth   : Vec<PostData>
+    pub replace_batch: Vec<ReplaceData>,
+    pub post_bacth   : Vec<PostData>,
+
+    // how the main loop waits when a full pass had no work
+    pub idle_wait    : WaitStrategy,
+    pub last_queue_heartbeat : u64,
+
+    // every timer reads this , a SimClock in replay and tests
+    pub clock : C,
+
+    // runtime tuning , each symbol gets its own resolved copy (`config.for_symbol`) when it is added
+    pub config : MmConfig,
+
+    // global stop , pulled by an api message , SIGUSR1 , the kill file or the aggregate pnl limit
+    pub kill_switch : KillSwitch,
+
+    // this run's client id epoch , 0 unless set with with_epoch
+    pub epoch : u16,
+
+    // reusable buffers for the batched queue reads and writes
+    pub fill_scratch  : Vec<MarketMakerFill>,
+    pub feed_scratch  : Vec<MarketMakerFeed>,
+    pub api_scratch   : Vec<MessageFromApi>,
+    pub order_scratch : Vec<MmOrder>,
   
 }
 
 impl MarketMaker{
-    pub fn new()->Self{
-        let fill_queue = MarketMakerFillQueue::open("/tmp/MarketMakerFills");
-        if fill_queue.is_err(){
-            eprintln!("failed to open the fill queue");
-        }
-        let feed_queue = MarketMakerFeedQueue::open("/tmp/MarketMakerFeed");
-        if feed_queue.is_err(){
-            eprint!("failed to open feed queue");
-        }
-        let order_queue = MarketMakerOrderQueue::open("/tmp/MarketMakerOrders");
-        if order_queue.is_err(){
-            eprint!("failed to open order queue");
-        }
-        let message_from_api_queueu = MessageFromApiQueue::open("/tmp/MessageFromApiToMM");
-        if message_from_api_queueu.is_err(){
-            eprint!("fai;ed to open message queue");
-        }
+    /// opens the four /tmp queues the engine created , fails if any of them is missing or does not validate
+    pub fn open_shm()->Result<Self , QueueError>{
+        let fill_queue = MarketMakerFillQueue::open("/tmp/MarketMakerFills")?;
+        let feed_queue = MarketMakerFeedQueue::open("/tmp/MarketMakerFeed")?;
+        let order_queue = MarketMakerOrderQueue::open("/tmp/MarketMakerOrders")?;
+        let message_from_api_queueu = MessageFromApiQueue::open("/tmp/MessageFromApiToMM")?;
+        Ok(Self::with_transport(feed_queue, fill_queue, message_from_api_queueu, order_queue, MonotonicClock::new()))
+    }
+}
+
+impl<Fe : FeedSource , Fi : FillSource , A : ApiSource , O : OrderSink , C : Clock> MarketMaker<Fe , Fi , A , O , C>{
+    pub fn with_transport(feed_queue : Fe , fill_queue : Fi , message_queue : A , order_queue : O , clock : C)->Self{
+        let now = clock.now_nanos();
         Self { 
             //symbol_orders : FxHashMap::with_capacity_and_hasher(MAX_SYMBOLS, Default::default()),
-            order_queue : order_queue.unwrap(),
-            fill_queue : fill_queue.unwrap(),
-            feed_queue : feed_queue.unwrap(),
-            message_queue : message_from_api_queueu.unwrap(),
+            order_queue,
+            fill_queue,
+            feed_queue,
+            message_queue,
             volitality_estimator: VolatilityEstimator::new() , 
             symbol_ctx : FxHashMap::with_capacity_and_hasher(MAX_SYMBOLS, Default::default()),
             //symbol_states : FxHashMap::with_capacity_and_hasher(MAX_SYMBOLS, Default::default())
             cancel_batch : Vec::with_capacity(4096),
+            replace_batch : Vec::with_capacity(4096),
             post_bacth : Vec::with_capacity(4096),
+            idle_wait : WaitStrategy::default(),
+            last_queue_heartbeat : now,
+            clock,
+            config : MmConfig::default(),
+            kill_switch : KillSwitch::default(),
+            epoch : 0,
+            fill_scratch : vec![MarketMakerFill::default(); DRAIN_BATCH_SIZE],
+            feed_scratch : Vec::with_capacity(DRAIN_BATCH_SIZE),
+            api_scratch : vec![MessageFromApi::default(); DRAIN_BATCH_SIZE],
+            order_scratch : Vec::with_capacity(4096),
+        }
+    }
+
+    /// replaces the tuning , symbols added before this keep the copy they were created with
+    pub fn with_config(mut self , config : MmConfig)->Self{
+        self.kill_switch.flag_file = config.kill_file.clone();
+        self.config = config;
+        self
+    }
+
+    /// client ids of symbols added from now on carry this epoch , see client_id::next_epoch
+    pub fn with_epoch(mut self , epoch : u16)->Self{
+        self.epoch = epoch;
+        self
+    }
+
+    // the symbol an ack or a reject belongs to : the one in the client id if the id is from this run ,
+    // the message's own symbol otherwise (cancel acks may only carry the exchange id)
+    fn route(&self , api_response : &MessageFromApi)->u32{
+        let client_id = ClientId::decode(api_response.client_id);
+        if api_response.client_id != 0 && client_id.epoch == self.epoch {
+            client_id.symbol as u32
+        } else {
+            api_response.symbol
         }
     }
+
+    /// reverse lookup : the order behind one of this run's client ids , only its own symbol is searched
+    pub fn find_order(&self , client_id : u64)->Option<&PendingOrder>{
+        let id = ClientId::decode(client_id);
+        if id.epoch != self.epoch {
+            return None;
+        }
+        self.symbol_ctx.get(&(id.symbol as u32))?.orders.get(client_id)
+    }
+
+    /// handle to pull the kill switch from outside the main loop , it is acted on at the next tick
+    pub fn kill_trigger(&self)->KillTrigger{
+        self.kill_switch.handle()
+    }
     #[inline(always)]
     pub fn update_state_from_feed(&mut self , market_feed : MarketMakerFeed)->Result<() , MmError>{
         let symbol = market_feed.symbol;
@@ -1021,33 +1250,42 @@ impl MarketMaker{
     }
 
 
+    // sequence check first , a stale fill was already applied once and must not move inventory again 
+    // on a gap the fill is still applied but the symbol is pushed into emergency until a reconcile sent after
+    // the gap comes back clean 
+    #[inline(always)]
+    pub fn process_fill(&mut self , market_fill : MarketMakerFill)->Result<() , MmError>{
+        let ctx = match self.symbol_ctx.get_mut(&market_fill.symbol){
+            Some(ctx) => ctx ,
+            None => return Err(MmError::SymbolNotFound)
+        };
+
+        let sequence = ctx.state.fill_seq.observe(market_fill.seq);
+        match sequence {
+            Err(MmError::StaleSequence { .. }) => return sequence,
+            Err(_) => {
+                ctx.state.fill_gap_detected = true;
+                ctx.state.fill_gap_query = ctx.orders.reconciler.next_request_id;
+            }
+            Ok(_) => {}
+        }
+
+        self.update_inventory_from_fill(market_fill)?;
+        // order manager update 
+        self.order_manager_update_after_fill(market_fill)?;
+        sequence
+    }
+
     #[inline(always)]
     pub fn order_manager_update_after_fill(&mut self , market_fill : MarketMakerFill)->Result<() , MmError>{
         let symbol = market_fill.symbol; 
         match self.symbol_ctx.get_mut(&symbol){
             Some(ctx)=>{
-                if let Some( mm_order) = ctx.orders.pending_orders.iter_mut().find(|pending_order| 
-                    match pending_order.exchange_order_id{
-                        Some(order_id)=>{
-                            order_id == market_fill.order_id_mm_order
-                        }
-                        None =>{
-                            false
-                        }
-                    }
-                ){
-                    mm_order.remaining_size = mm_order.remaining_size.saturating_sub(market_fill.fill_quantity);
-
-                    if mm_order.remaining_size == 0{
-                        mm_order.state = OrderState::CompletelyFilled
-                    }else{
-                        mm_order.state = OrderState::PartiallyFilled
-                    }
-
-
+                let filled = ctx.orders.update_by_exchange_id(market_fill.order_id_mm_order, |mm_order| mm_order.apply_fill(market_fill.fill_quantity));
+                // we can remove the order once its fully matched 
+                if filled == Some(true) {
+                    ctx.orders.remove_by_exchange_id(market_fill.order_id_mm_order);
                 }
-                // we can remove the orders which are fully matched 
-                ctx.orders.pending_orders.retain(|order| order.remaining_size > 0 );
             }
             None=>{
                 return Err(MmError::SymbolNotFound);
@@ -1068,22 +1306,33 @@ impl MarketMaker{
             }
 
             None=>{
-                return Err(MmError::SymbolNotFound);
+                Err(MmError::SymbolNotFound)
             }
         }
     }
     #[inline(always)]
-    pub fn handle_order_acceptance_ack(&mut self  , api_response : MessageFromApi)->Result<() , MmError>{
-        let symbol = api_response.symbol;
+    pub fn handle_order_acceptance_ack(&mut self  , api_response : MessageFromApi , now : u64)->Result<() , MmError>{
+        let symbol = self.route(&api_response);
         match self.symbol_ctx.get_mut(&symbol){
             Some(ctx)=>{
-                if let Some(order) = ctx.orders.pending_orders.iter_mut().find(
-                    |pending_order|
-                    pending_order.client_id == api_response.client_id
-
-                ){
+                let cancel_batch = &mut self.cancel_batch;
+                let acked = ctx.orders.update(api_response.client_id, |order| {
                     order.exchange_order_id = Some(api_response.order_id);
                     order.state = OrderState::Active;
+                    order.stuck_since = None;
+                    // a cancel came in while we were waiting for this ack 
+                    if order.cancel_on_ack {
+                        cancel_batch.push(CancelData { symbol, client_id: order.client_id, order_id: Some(api_response.order_id) });
+                        order.mark_pending_cancel(now);
+                    }
+                });
+                if acked.is_some() {
+                    ctx.orders.rejects.accepted();
+                } else if let Some(ghost) = ctx.orders.ghosts.iter_mut().find(|ghost| ghost.client_id == api_response.client_id) {
+                    // a post we had given up on did make it , it is cancelled straight away
+                    ghost.order_id = Some(api_response.order_id);
+                    ghost.cancel_sent_at = Some(now);
+                    self.cancel_batch.push(CancelData { symbol, client_id: ghost.client_id, order_id: Some(api_response.order_id) });
                 }
             }
             None=>{
@@ -1092,13 +1341,128 @@ impl MarketMaker{
         }
         Ok(())
     }
+    // the engine moved the order , it may have given it a new exchange id , shares_qty is what rests now
+    pub fn handle_order_replaced_ack(&mut self , api_response : MessageFromApi , now : u64)->Result<() , MmError>{
+        let symbol = self.route(&api_response);
+        let ctx = self.symbol_ctx.get_mut(&symbol).ok_or(MmError::SymbolNotFound)?;
+        let cancel_batch = &mut self.cancel_batch;
+        let filled = ctx.orders.update(api_response.client_id, |order| {
+            order.exchange_order_id = Some(api_response.order_id);
+            order.replaced_from = None;
+            order.stuck_since = None;
+            order.remaining_size = api_response.shares_qty.min(order.original_size);
+            order.state = if order.remaining_size < order.original_size { OrderState::PartiallyFilled } else { OrderState::Active };
+            if order.cancel_on_ack {
+                cancel_batch.push(CancelData { symbol, client_id: order.client_id, order_id: Some(api_response.order_id) });
+                order.mark_pending_cancel(now);
+            }
+            order.remaining_size == 0
+        });
+        if let Some(filled) = filled {
+            // filled while the amend was on its way
+            if filled {
+                ctx.orders.remove(api_response.client_id);
+            }
+            ctx.orders.rejects.accepted();
+        }
+        Ok(())
+    }
+
+    // a rejected post never rested , it is dropped so it stops counting towards the ladder
+    // a rejected cancel leaves the order resting (Active again , the next cancel pass retries it) , a rejected
+    // amend leaves it at its old price and size
+    // unless the engine does not know the order any more
+    pub fn handle_order_reject(&mut self , api_response : MessageFromApi , now : u64)->Result<() , MmError>{
+        let symbol = self.route(&api_response);
+        let ctx = self.symbol_ctx.get_mut(&symbol).ok_or(MmError::SymbolNotFound)?;
+        let reason = RejectReason::from_u8(api_response.reject_reason);
+        let still_resting = ctx.orders.update(api_response.client_id, |order| {
+            if order.state == OrderState::PendingCancel && reason != RejectReason::UnknownOrder {
+                order.state = if order.remaining_size < order.original_size { OrderState::PartiallyFilled } else { OrderState::Active };
+                true
+            } else if order.state == OrderState::PendingReplace && reason != RejectReason::UnknownOrder {
+                // the amend was refused , the order still rests as it was (less what filled meanwhile)
+                order.revert_replace();
+                order.remaining_size > 0
+            } else {
+                false
+            }
+        });
+        if still_resting == Some(false) {
+            ctx.orders.remove(api_response.client_id);
+        }
+        // a refused post never rested , a cancel the engine has no order for is done
+        ctx.orders.ghosts.retain(|ghost| ghost.client_id != api_response.client_id || (ghost.order_id.is_some() && reason != RejectReason::UnknownOrder));
+        if let Some(backoff) = ctx.orders.rejects.record(reason, now) {
+            eprintln!(" symbol {} rejected {} times in a row ({:?}) , not quoting for {:?}" , symbol , ctx.orders.rejects.consecutive , reason , backoff);
+        }
+        Ok(())
+    }
+
+    // fixes our view of the symbol's orders from the engine's snapshot , every difference is logged
+    // a fill that still has not shown up a whole reconcile later went missing , the inventory is untrusted until a later
+    // reconcile comes back clean
+    pub fn handle_open_orders_end(&mut self , api_message : MessageFromApi , now : u64)->usize{
+        let symbol = api_message.symbol;
+        let Some(ctx) = self.symbol_ctx.get_mut(&symbol) else {
+            return 0;
+        };
+        let completes = ctx.orders.reconciler.in_flight.is_some_and(|(request_id , _)| request_id == api_message.order_id);
+        let found = reconcile(&mut ctx.orders, &api_message, &mut self.cancel_batch, now);
+        for discrepancy in &found {
+            eprintln!(" reconcile symbol {} {:?}" , symbol , discrepancy);
+            if matches!(discrepancy , Discrepancy::QtyMismatch { .. }) {
+                ctx.state.fill_gap_detected = true;
+                ctx.state.fill_gap_query = api_message.order_id;
+            }
+        }
+        // the engine agrees with every order we have , the book is trusted again
+        if completes && found.is_empty() && ctx.state.fill_gap_detected && api_message.order_id > ctx.state.fill_gap_query {
+            ctx.state.fill_gap_detected = false;
+            eprintln!(" symbol {} reconciled clean after a fill gap" , symbol);
+        }
+        found.len()
+    }
+
+    // UpdateParam from the api : client_id carries the parameter id and ipo_price the value
+    // (fixed point , PARAM_VALUE_SCALE decimals) , order_id is echoed back in the ack
+    pub fn handle_param_update(&mut self , api_message : MessageFromApi)->Result<() , MmError>{
+        let ctx = match self.symbol_ctx.get_mut(&api_message.symbol){
+            Some(ctx) => ctx ,
+            None => return Err(MmError::SymbolNotFound)
+        };
+        let param = StrategyParam::from_id(api_message.client_id)
+            .ok_or_else(|| MmError::InvalidParam(format!("unknown parameter id {}", api_message.client_id)))?;
+        // sent as the bits of an i64 , the pnl limits are negative 
+        let value = Decimal::new(api_message.ipo_price as i64, PARAM_VALUE_SCALE).normalize();
+        ctx.stage_param(param, value)
+    }
+
+    pub fn send_control_ack(&mut self , api_message : MessageFromApi , accepted : bool){
+        let ack = MmOrder { 
+            order_id : api_message.order_id, 
+            client_id : api_message.client_id, 
+            price : api_message.ipo_price, 
+            timestamp : 0, 
+            shares_qty : 0, 
+            symbol : api_message.symbol, 
+            side : 2, 
+            order_type : 2, 
+            status : if accepted { 0 } else { 2 }
+        };
+        if let Err(queue_error) = self.order_queue.send(ack){
+            eprintln!(" enqueue erro , control ack {:?}" , queue_error);
+        }
+    }
+
     #[inline(always)]
     pub fn handle_order_cancel_ack(&mut self, api_response : MessageFromApi)->Result<() , MmError>{
-        let symbol = api_response.symbol;
+        let symbol = self.route(&api_response);
         match self.symbol_ctx.get_mut(&symbol){
             Some(ctx)=>{
-                // remove it now , 
-                ctx.orders.pending_orders.retain(|order| order.exchange_order_id != Some(api_response.order_id) && order.state == OrderState::PendingCancel);
+                // remove it now , only the acked order , everything else stays
+                ctx.orders.remove_by_exchange_id(api_response.order_id);
+                ctx.orders.ghosts.retain(|ghost| ghost.order_id != Some(api_response.order_id));
                 
             }
             None=>{
@@ -1108,13 +1472,14 @@ impl MarketMaker{
         Ok(())
     }
 
-    pub fn check_if_depth_update_causes_cancellation(&mut self , symbol : u32){
+    pub fn check_if_depth_update_causes_cancellation(&mut self , symbol : u32 , now : u64){
         
         let symbol_context = match self.symbol_ctx.get_mut(&symbol){
             Some(ctx)=>ctx ,
             None => return 
         };
 
+        let cancel_batch = &mut self.cancel_batch;
         let mid_price_move = (symbol_context.state.market_state.mid_price - symbol_context.state.prev_mid_price).abs();
         // percentage change in price 
         //let price_move_pct = if symbol_context.state.prev_mid_price != dec!(0) {
@@ -1123,46 +1488,45 @@ impl MarketMaker{
         //    0.0
         //};
 
-        let mid_price_move_in_ticks = mid_price_move/TICK_SIZE;
+        let mid_price_move_in_ticks = mid_price_move/symbol_context.state.config.tick_size;
+        let mid_price = symbol_context.state.market_state.mid_price;
 
         if mid_price_move_in_ticks >= dec!(3) {  
-            for order in &mut symbol_context.orders.pending_orders {
+            symbol_context.orders.for_each_mut(|order| {
                 if order.state != OrderState::Active {
-                    continue;
+                    return;
                 }
                 // cancellation when there becomes no chance of matching 
                 // doing only urgent and instantanoues cancellations here , that definately need to be cancelled 
                 // the orders are crossing the market 
                 let should_cancel = match order.side {
-                    Side::BID => order.price > symbol_context.state.market_state.mid_price,  // Bid above mid
-                    Side::ASK => order.price < symbol_context.state.market_state.mid_price,  // Ask below mid
+                    Side::BID => order.price > mid_price,  // Bid above mid
+                    Side::ASK => order.price < mid_price,  // Ask below mid
                 };
                 
-                if should_cancel {
-                    if let Some(order_id) = order.exchange_order_id {
-                        // send cancellation request 
-                        self.cancel_batch.push(CancelData { symbol, client_id: order.client_id, order_id  : Some(order_id) });
-                        order.state = OrderState::PendingCancel;
-                    }
+                if should_cancel
+                    && let Some(order_id) = order.exchange_order_id {
+                    // send cancellation request 
+                    cancel_batch.push(CancelData { symbol, client_id: order.client_id, order_id  : Some(order_id) });
+                    order.mark_pending_cancel(now);
                 }
                 // stale orders getting canclled before we requote 
-            }
+            });
         }
 
 
         let current_spread = symbol_context.state.best_ask - symbol_context.state.best_bid;
-        let spread_in_ticks = current_spread/TICK_SIZE;
+        let spread_in_ticks = current_spread/symbol_context.state.config.tick_size;
 
-        if spread_in_ticks < MIN_PROFITABLE_SPREAD_IN_TICKS {  
+        if spread_in_ticks < symbol_context.state.config.min_profitable_spread_in_ticks {  
             
-            for order in &mut symbol_context.orders.pending_orders {
-                if order.state == OrderState::Active {
-                    if let Some(order_id) = order.exchange_order_id {
-                        self.cancel_batch.push(CancelData { symbol, client_id: order.client_id, order_id  : Some(order_id) });
-                        order.state = OrderState::PendingCancel;
-                    }
+            symbol_context.orders.for_each_mut(|order| {
+                if order.state == OrderState::Active
+                    && let Some(order_id) = order.exchange_order_id {
+                    cancel_batch.push(CancelData { symbol, client_id: order.client_id, order_id  : Some(order_id) });
+                    order.mark_pending_cancel(now);
                 }
-            }
+            });
             return;  // No need to check other triggers
         }
         
@@ -1173,36 +1537,34 @@ impl MarketMaker{
             if bid_depth_ratio < 0.3 {  // 70% of depth gone
                 
                 
-                for order in &mut symbol_context.orders.pending_orders {
-                    if order.side == Side::BID && order.state == OrderState::Active {
-                        if let Some(order_id) = order.exchange_order_id {
-                            self.cancel_batch.push(CancelData { symbol, client_id: order.client_id, order_id  : Some(order_id) });
-                            order.state = OrderState::PendingCancel;
-                        }
+                symbol_context.orders.for_each_mut(|order| {
+                    if order.side == Side::BID && order.state == OrderState::Active
+                        && let Some(order_id) = order.exchange_order_id {
+                        cancel_batch.push(CancelData { symbol, client_id: order.client_id, order_id  : Some(order_id) });
+                        order.mark_pending_cancel(now);
                     }
-                }
+                });
             }
         }
         if symbol_context.state.prev_best_ask_qty > 0 {
             let ask_depth_ratio = symbol_context.state.best_ask_qty as f64 / symbol_context.state.prev_best_ask_qty as f64;
             
             if ask_depth_ratio < 0.3 {
-                for order in &mut symbol_context.orders.pending_orders {
-                    if order.side == Side::ASK && order.state == OrderState::Active {
-                        if let Some(order_id) = order.exchange_order_id {
-                           //self.send_cancel_request(symbol, order.client_id, order_id);
-                            self.cancel_batch.push(CancelData { symbol, client_id: order.client_id, order_id  : Some(order_id) });
-                            order.state = OrderState::PendingCancel;
-                        }
+                symbol_context.orders.for_each_mut(|order| {
+                    if order.side == Side::ASK && order.state == OrderState::Active
+                        && let Some(order_id) = order.exchange_order_id {
+                       //self.send_cancel_request(symbol, order.client_id, order_id);
+                        cancel_batch.push(CancelData { symbol, client_id: order.client_id, order_id  : Some(order_id) });
+                        order.mark_pending_cancel(now);
                     }
-                }
+                });
             }
         }
     }
 
 
     pub fn send_cancel_request(&mut self , symbol : u32 , client_id : u64 , order_id : u64 )->Result<() , QueueError>{
-        match self.order_queue.enqueue(MmOrder { 
+        match self.order_queue.send(MmOrder { 
             order_id, 
             client_id, 
             price: 0, 
@@ -1224,32 +1586,31 @@ impl MarketMaker{
     }
 
     pub fn send_post_request(&mut self , symbol : u32 , price : Decimal , qty: u32, side : Side)->Result<() , QueueError>{
-        match self.symbol_ctx.get_mut(&symbol){
-            Some(ctx)=>{
-                match self.order_queue.enqueue(MmOrder { 
-                    order_id : 0 , 
-                    client_id : ctx.orders.alloc_client_id() , 
-                    price : price.to_u64().unwrap(), 
-                    timestamp: 0, 
-                    shares_qty: qty, 
-                    symbol, 
-                    side: match side {
-                        Side::ASK => 1 ,
-                        Side::BID => 0 
-                    }, 
-                    order_type: 0, 
-                    status: 0
-                }){
-                    Ok(_)=>{
-        
-                    }
-                    Err(queue_error)=>{
-                        eprintln!(" enqueue erro {:?}" , queue_error);
-                    }
+        let Some(wire_price) = wire_price(price) else {
+            eprintln!(" symbol {} post at {} skipped , price out of range" , symbol , price);
+            return Ok(());
+        };
+        if let Some(ctx) = self.symbol_ctx.get_mut(&symbol) {
+            match self.order_queue.send(MmOrder { 
+                order_id : 0 , 
+                client_id : ctx.orders.alloc_client_id() , 
+                price : wire_price, 
+                timestamp: 0, 
+                shares_qty: qty, 
+                symbol, 
+                side: match side {
+                    Side::ASK => 1 ,
+                    Side::BID => 0 
+                }, 
+                order_type: 0, 
+                status: 0
+            }){
+                Ok(_)=>{
+    
+                }
+                Err(queue_error)=>{
+                    eprintln!(" enqueue erro {:?}" , queue_error);
                 }
-            }
-            None =>{
-
             }
         }
         
@@ -1264,258 +1625,1230 @@ impl MarketMaker{
             }
         };
 
-        let bids = ctx.orders.pending_orders.iter().filter(
-            |order|
-            order.side == Side::BID && order.state == OrderState::Active
-        ).count();
-
-        let asks = ctx.orders.pending_orders.iter().filter(
-            |order|
-            order.side == Side::ASK && order.state == OrderState::Active
-        ).count();
+        let bids = ctx.orders.count(Side::BID, OrderState::Active);
+        let asks = ctx.orders.count(Side::ASK, OrderState::Active);
 
         Ok((bids , asks ))
     }
 
-    pub fn cancel_all_orders(&mut self , _ : u32){
+    // both of these only queue the cancels , they go out with the cancel batch at the end of the next tick
 
+    pub fn cancel_all_orders(&mut self , symbol : u32)->Result<usize , MmError>{
+        let ctx = self.symbol_ctx.get_mut(&symbol).ok_or(MmError::SymbolNotFound)?;
+        Ok(ctx.cancel_orders(symbol, None, &mut self.cancel_batch, self.clock.now_nanos()))
     }
 
-    pub fn cancel_side(&mut self , _ : u32){
+    pub fn cancel_side(&mut self , symbol : u32 , side : Side)->Result<usize , MmError>{
+        let ctx = self.symbol_ctx.get_mut(&symbol).ok_or(MmError::SymbolNotFound)?;
+        Ok(ctx.cancel_orders(symbol, Some(side), &mut self.cancel_batch, self.clock.now_nanos()))
+    }
 
+    // pulls the kill switch if any of its sources fired , the api message is handled with the other messages
+    fn poll_kill_sources(&mut self , now : u64){
+        if self.kill_switch.is_triggered() {
+            return;
+        }
+        if signals::take(libc::SIGUSR1) {
+            self.kill_switch.trigger.trigger(KillReason::Signal);
+            return;
+        }
+        if self.kill_switch.flag_file.is_some() && elapsed(now, self.kill_switch.last_flag_check) >= KILL_FILE_CHECK_GAP {
+            self.kill_switch.last_flag_check = now;
+            if self.kill_switch.flag_file_present() {
+                self.kill_switch.trigger.trigger(KillReason::FileFlag);
+                return;
+            }
+        }
+        let aggregate_pnl : Decimal = self.symbol_ctx.values().map(|ctx| ctx.state.pnl.total).sum();
+        if aggregate_pnl < self.config.max_allowed_neg_aggregate_pnl {
+            eprintln!(" aggregate pnl {} below {} " , aggregate_pnl , self.config.max_allowed_neg_aggregate_pnl);
+            self.kill_switch.trigger.trigger(KillReason::PnlBreach);
+        }
     }
 
-    // market maker running looop
+    // every symbol is halted and cancelled out , repeated on every tick once pulled
+    // so orders that get acked or partially filled in the meantime and symbols added later are caught as well
+    fn run_kill_switch(&mut self , summary : &mut TickSummary , now : u64){
+        let Some(reason) = self.kill_switch.trigger.reason() else {
+            return;
+        };
+        if !self.kill_switch.engaged {
+            eprintln!(" kill switch pulled {:?} , cancelling {} symbols" , reason , self.symbol_ctx.len());
+            self.kill_switch.engaged = true;
+            summary.kill_engaged = Some(reason);
+        }
+        for (symbol , ctx) in self.symbol_ctx.iter_mut() {
+            ctx.state.halted = true;
+            if ctx.state.current_mode != QuotingMode::Emergency {
+                summary.mode_changes.push(ModeChange { symbol : *symbol , from : ctx.state.current_mode , to : QuotingMode::Emergency });
+                ctx.state.prev_mode = ctx.state.current_mode;
+                ctx.state.current_mode = QuotingMode::Emergency;
+            }
+            ctx.cancel_orders(*symbol, None, &mut self.cancel_batch, now);
+        }
+    }
 
-    pub fn run_market_maker(&mut self){
-        loop{
-            // clear the two batches 
-            self.cancel_batch.clear();
-            self.post_bacth.clear();
+    // called after the batches are sent , done once no symbol has an order left on the engine
+    fn check_kill_completion(&mut self , summary : &mut TickSummary){
+        if !self.kill_switch.engaged || self.kill_switch.completed {
+            return;
+        }
+        // ghosts count as well , they may still rest on the engine
+        if self.symbol_ctx.values().any(|ctx| !ctx.orders.is_empty() || !ctx.orders.ghosts.is_empty()) {
+            return;
+        }
+        self.kill_switch.completed = true;
+        summary.kill_completed = true;
+        eprintln!(" kill switch complete , no orders left on {} symbols" , self.symbol_ctx.len());
+        for request in std::mem::take(&mut self.kill_switch.requests) {
+            self.send_control_ack(request, true);
+        }
+    }
 
+    /// One full pass : drain fills , feed and api messages , run the per symbol
+    /// management cycle and send the cancel and post batches.
+    /// `now` is a reading of `self.clock` (nanos) and drives every timer the pass looks at ,
+    /// so a simulator or a test can control time.
+    pub fn tick(&mut self , now : u64)->TickSummary{
+        // the batches are cleared once they are sent , cancels queued between ticks
+        // (cancel_all_orders / cancel_side) go out with this one 
+        let mut summary = TickSummary::default();
+
+        // let the engine and any external tooling see that our ends of the queues are alive 
+        if elapsed(now, self.last_queue_heartbeat) >= QUEUE_HEARTBEAT_GAP {
+            self.fill_queue.heartbeat();
+            self.feed_queue.heartbeat();
+            self.message_queue.heartbeat();
+            self.order_queue.heartbeat();
+            self.last_queue_heartbeat = now;
+        }
 
-            // now needing to process fills an order got matched for the market maker 
-            while let Ok(Some(fill)) = self.fill_queue.dequeue(){
-                let _= self.update_inventory_from_fill(fill);
-                // order manager update 
-                let _ = self.order_manager_update_after_fill(fill);
-            }
+        // every queue is drained in runs of DRAIN_BATCH_SIZE , one tail publish per run
+        // the scratch buffers are taken out of self so the handlers can borrow self mutably
 
-            // HANDLE ALL THE EVENTS WE RECEIVE 
-            // first we consume the feed from the engine 
-            while let Ok(Some(feed)) = self.feed_queue.dequeue(){
+        // now needing to process fills an order got matched for the market maker 
+        let mut fills = std::mem::take(&mut self.fill_scratch);
+        loop {
+            let n = self.fill_queue.recv_batch(&mut fills);
+            for fill in &fills[..n] {
+                if let Err(error) = self.process_fill(*fill){
+                    eprintln!(" fill error symbol {} {:?}" , fill.symbol , error);
+                }
+            }
+            summary.fills += n;
+            if n < fills.len() {
+                break;
+            }
+        }
+        self.fill_scratch = fills;
+
+        // HANDLE ALL THE EVENTS WE RECEIVE 
+        // first we consume the feed from the engine 
+        // records are looked at in place , feeds for symbols we dont quote are skipped without a copy
+        // and back to back updates for the same bootstrapped symbol are conflated into the latest one
+        // (bootstrap infers trades from every depth change so those are all kept)
+        let mut feeds = std::mem::take(&mut self.feed_scratch);
+        loop {
+            feeds.clear();
+            let symbol_ctx = &mut self.symbol_ctx;
+            let consumed = self.feed_queue.consume_while(|feed| {
+                let Some(ctx) = symbol_ctx.get_mut(&feed.symbol) else {
+                    return true;
+                };
+                let conflate = feeds.last().is_some_and(|last| last.symbol == feed.symbol) && ctx.state.is_bootstrapped;
+                if !conflate && feeds.len() == DRAIN_BATCH_SIZE {
+                    // batch is full , leave this one for the next round
+                    // before its sequence is looked at , the next round sees it again
+                    return false;
+                }
+                // sequence is checked on every raw record , before any conflation 
+                match ctx.state.feed_seq.observe(feed.seq) {
+                    // older than what we already have , drop it 
+                    Err(MmError::StaleSequence { .. }) => return true,
+                    // feed records are full snapshots , the newest one is still good to apply 
+                    Err(error) => eprintln!(" feed sequence symbol {} {:?}" , feed.symbol , error),
+                    Ok(_) => {}
+                }
+                match feeds.last_mut() {
+                    Some(last) if conflate => *last = *feed,
+                    _ => feeds.push(*feed),
+                }
+                true
+            });
+            for feed in &feeds {
                 let symbol = feed.symbol;
                 // update the feed for that symbol 
-                match self.update_state_from_feed(feed){
+                match self.update_state_from_feed(*feed){
                     Ok(_)=>{
-                        self.check_if_depth_update_causes_cancellation(symbol);
+                        self.check_if_depth_update_causes_cancellation(symbol, now);
                     }
                     Err(error)=>{
                         eprintln!(" feed update error {:?}" , error);
                     }
                 }
             }
+            summary.feeds += consumed;
+            if feeds.len() < DRAIN_BATCH_SIZE {
+                break;
+            }
+        }
+        self.feed_scratch = feeds;
 
-            while let Ok(Some(api_message)) = self.message_queue.dequeue(){
+        let mut api_messages = std::mem::take(&mut self.api_scratch);
+        loop {
+            let n = self.message_queue.recv_batch(&mut api_messages);
+            for api_message in &api_messages[..n] {
+                let api_message = *api_message;
                 let symbol = api_message.symbol;
                 match api_message.message_type{
                     0 =>{
                         // adding thr symbol , directly adding the context 
-                        self.symbol_ctx.insert(symbol, SymbolContext::new(Decimal::from(api_message.ipo_price), symbol));
+                        // the symbol is part of every client id , it has to fit (see client_id.rs)
+                        if symbol > MAX_CLIENT_ID_SYMBOL {
+                            eprintln!(" symbol {} rejected , ids above {} dont fit in a client id" , symbol , MAX_CLIENT_ID_SYMBOL);
+                            summary.symbols_rejected += 1;
+                            continue;
+                        }
+                        // listing specific parameters if the config has any for this symbol 
+                        let config = match self.config.for_symbol(symbol){
+                            Ok(config) => config ,
+                            Err(error) => {
+                                eprintln!(" symbol {} config {} , using the defaults" , symbol , error);
+                                self.config.defaults
+                            }
+                        };
+                        // added after a kill , it is halted with the rest at the kill switch step below
+                        self.symbol_ctx.insert(symbol, SymbolContext::new(Decimal::from(api_message.ipo_price), symbol, now, config, self.epoch));
                     }
                     1 =>{
-                        // order accepted ack
-                        self.handle_order_acceptance_ack(api_message).expect("coulndt handle the order acceptance ");
+                        // order accepted ack , one for a symbol we dont have is left over from a previous run
+                        // or came in before the symbol was added again
+                        if let Err(error) = self.handle_order_acceptance_ack(api_message, now){
+                            eprintln!(" accept ack symbol {} {:?}" , symbol , error);
+                            summary.unknown_acks += 1;
+                        }
                     }
                     2=>{
                         // cancale ordr ack
-                        self.handle_order_cancel_ack(api_message).expect("coundt handle the order cancellation ack ")
+                        if let Err(error) = self.handle_order_cancel_ack(api_message){
+                            eprintln!(" cancel ack symbol {} {:?}" , symbol , error);
+                            summary.unknown_acks += 1;
+                        }
+                    }
+                    3=>{
+                        // parameter update , acked now , applied at the symbol's next management cycle 
+                        let result = self.handle_param_update(api_message);
+                        if let Err(error) = &result {
+                            eprintln!(" param update rejected symbol {} {:?}" , symbol , error);
+                        }
+                        self.send_control_ack(api_message, result.is_ok());
+                    }
+                    4=>{
+                        // kill switch , acked once every symbol is out of the book 
+                        self.kill_switch.trigger.trigger(KillReason::ControlMessage);
+                        if self.kill_switch.completed {
+                            self.send_control_ack(api_message, true);
+                        } else {
+                            self.kill_switch.requests.push(api_message);
+                        }
+                    }
+                    5=>{
+                        // order rejected 
+                        if let Err(error) = self.handle_order_reject(api_message, now){
+                            eprintln!(" order reject symbol {} {:?}" , symbol , error);
+                        }
+                        summary.rejects += 1;
+                    }
+                    6=>{
+                        // one of our open orders , part of a reconcile snapshot
+                        if let Some(ctx) = self.symbol_ctx.get_mut(&symbol) {
+                            ctx.orders.reconciler.on_entry(&api_message);
+                        }
+                    }
+                    7=>{
+                        // snapshot complete , diff it against ours 
+                        summary.discrepancies += self.handle_open_orders_end(api_message, now);
+                    }
+                    8=>{
+                        // amend went through 
+                        if let Err(error) = self.handle_order_replaced_ack(api_message, now){
+                            eprintln!(" replace ack symbol {} {:?}" , symbol , error);
+                            summary.unknown_acks += 1;
+                        }
                     }
                     _=>{
 
                     }
                 }
             }
+            summary.api_messages += n;
+            if n < api_messages.len() {
+                break;
+            }
+        }
+        self.api_scratch = api_messages;
 
+        self.poll_kill_sources(now);
+        self.run_kill_switch(&mut summary, now);
 
 
 
-            // updating the steate loop
-            for (symbol  , ctx) in self.symbol_ctx.iter_mut(){
-                let deref_symbol = *symbol;
-                if ctx.state.last_sample_time.elapsed() >= SAMPLE_GAP{
-                    ctx.state.rolling_prices.push(ctx.state.market_state.mid_price);
-                    ctx.state.last_sample_time = Instant::now();
-                }
+        // updating the steate loop
+        for (symbol  , ctx) in self.symbol_ctx.iter_mut(){
+            let deref_symbol = *symbol;
+            if elapsed(now, ctx.state.last_sample_time) >= self.config.sample_gap{
+                ctx.state.rolling_prices.push(ctx.state.market_state.mid_price);
+                ctx.state.last_sample_time = now;
+            }
 
-                if ctx.state.last_volatility_calc.elapsed() >= VOLITILTY_CALC_GAP{
-                    let new_vol = self.volitality_estimator.calculate_simple(ctx.state.rolling_prices.as_slice_for_volatility());
-                    if new_vol.is_err(){
-                        eprint!("error in volatility calc");
+            // the estimator needs at least 3 samples (it divides by returns - 1) , keep the old value until then
+            if elapsed(now, ctx.state.last_volatility_calc) >= self.config.volatility_calc_gap
+                && ctx.state.rolling_prices.len() > 2 {
+                match self.volitality_estimator.calculate_simple(ctx.state.rolling_prices.as_slice_for_volatility()){
+                    Ok(new_vol)=>{
+                        ctx.state.market_state.volatility = new_vol;
+                    }
+                    Err(error)=>{
+                        eprintln!("error in volatility calc symbol {} {:?}" , deref_symbol , error);
                     }
-                    ctx.state.market_state.volatility = new_vol.unwrap();
-                    ctx.state.last_volatility_calc = Instant::now();
                 }
+                ctx.state.last_volatility_calc = now;
+            }
 
-                if ctx.state.last_management_cycle_time.elapsed() >= MANAGEMENT_CYCLE_GAP{
-                    for active_order in &mut ctx.orders.pending_orders{
-                       match ctx.state.should_cancel_unprofitable_order(active_order, ctx.state.market_state.mid_price, ctx.state.best_ask - ctx.state.best_bid){
-                            true =>{
-                                if active_order.state == OrderState::Active{
-                                    self.cancel_batch.push(CancelData { symbol : *symbol , client_id: active_order.client_id, order_id: active_order.exchange_order_id });
-                                    active_order.state = OrderState::PendingCancel;
-                                }
-                              // can safely unwrap iguess // but we can have a case , where the order ack dint come and we are 
-                              // on a stage of cancelling , keep option itself , can check when we enqueue 
-                            }
-                            false=>{
-
-                            }
-                       }
-
-                       match ctx.state.should_cancel_due_to_inventory(active_order ,  ctx.state.inventory.quantity){
-                            true =>{
-                                if active_order.state == OrderState::Active{
-                                    self.cancel_batch.push(CancelData { symbol : *symbol , client_id: active_order.client_id, order_id: active_order.exchange_order_id });
-                                    active_order.state = OrderState::PendingCancel;
-                                }
-                              // can safely unwrap iguess // but we can have a case , where the order ack dint come and we are 
-                              // on a stage of cancelling , keep option itself , can check when we enqueue 
-                            }
-                        
-                            false=>{
-                            
-                            }
-                        }
+            if elapsed(now, ctx.state.last_management_cycle_time) >= self.config.management_cycle_gap{
+                ctx.apply_staged_config();
+
+                let state = &ctx.state;
+                let cancel_batch = &mut self.cancel_batch;
+                ctx.orders.for_each_mut(|active_order| {
+                    // can safely unwrap iguess // but we can have a case , where the order ack dint come and we are 
+                    // on a stage of cancelling , keep option itself , can check when we enqueue 
+                    if state.should_cancel_unprofitable_order(active_order, state.market_state.mid_price, state.best_ask - state.best_bid)
+                        && active_order.state == OrderState::Active{
+                        cancel_batch.push(CancelData { symbol : deref_symbol , client_id: active_order.client_id, order_id: active_order.exchange_order_id });
+                        active_order.mark_pending_cancel(now);
                     }
 
+                    if state.should_cancel_due_to_inventory(active_order ,  state.inventory.quantity)
+                        && active_order.state == OrderState::Active{
+                        cancel_batch.push(CancelData { symbol : deref_symbol , client_id: active_order.client_id, order_id: active_order.exchange_order_id });
+                        active_order.mark_pending_cancel(now);
+                    }
+                });
 
-                    // this is very rare that this function wuld get ca;;ed , its just a cleanup function 
-                    ctx.check_if_time_caused_cancellation(*symbol, &mut self.cancel_batch);
-                    
-                    
 
-                    if !ctx.state.is_bootstrapped && ctx.state.should_exit_bootstrap() {
-                        ctx.state.is_bootstrapped = true;
+                // this is very rare that this function wuld get ca;;ed , its just a cleanup function 
+                ctx.check_if_time_caused_cancellation(*symbol, &mut self.cancel_batch, now);
+                summary.ack_timeouts += ctx.check_ack_timeouts(deref_symbol, &mut self.cancel_batch, now, &self.config.ack_timeouts);
+
+                // ask the engine for its view of our orders , the answer is diffed when its end marker comes in
+                if let Some(request_id) = ctx.orders.reconciler.start(now, self.config.reconcile_gap) {
+                    let query = MmOrder { 
+                        order_id : request_id, 
+                        client_id : 0, 
+                        price : 0, 
+                        timestamp : 0, 
+                        shares_qty : 0, 
+                        symbol : deref_symbol, 
+                        side : 2, 
+                        order_type : 3, 
+                        status : 0
+                    };
+                    if let Err(queue_error) = self.order_queue.send(query){
+                        eprintln!(" enqueue erro , open orders query {:?}" , queue_error);
+                        ctx.orders.reconciler.in_flight = None;
                     }
+                }
+                
+                
 
-                    let _ = ctx.state.determine_mode(); // no need to return , just update the mode 
-                    // can return emergency or invetnory capped also 
+                if !ctx.state.is_bootstrapped && ctx.state.should_exit_bootstrap() {
+                    ctx.state.is_bootstrapped = true;
+                }
 
+                let from = ctx.state.current_mode;
+                let to = ctx.state.determine_mode(); // no need to return , just update the mode 
+                if to != from {
+                    summary.mode_changes.push(ModeChange { symbol : deref_symbol , from , to });
+                    // get out of the book on the way into the protective modes 
+                    match to {
+                        QuotingMode::Emergency => {
+                            ctx.cancel_orders(deref_symbol, None, &mut self.cancel_batch, now);
+                        }
+                        QuotingMode::InventoryCapped { side : InventorySatus::Long } => {
+                            ctx.cancel_orders(deref_symbol, Some(Side::BID), &mut self.cancel_batch, now);
+                        }
+                        QuotingMode::InventoryCapped { side : InventorySatus::Short } => {
+                            ctx.cancel_orders(deref_symbol, Some(Side::ASK), &mut self.cancel_batch, now);
+                        }
+                        _ => {}
+                    }
+                }
+                // can return emergency or invetnory capped also 
 
-                    if ctx.should_requote(){
-                        // we compute target laders and try to modify them 
-                        match ctx.compute_target_ladder(){
-                            Ok(mut target_ladder)=>{
-                               if let Ok(requote_result) = ctx.incremental_requote(&mut target_ladder , *symbol){
-                                    let orders_to_cancel = requote_result.0;
-                                    let orders_to_post = requote_result.1;
 
-                                    for order in orders_to_cancel {
-                                        self.cancel_batch.push(CancelData { symbol : deref_symbol , client_id: order.1, order_id: Some(order.0) });
-                                    }
+                if ctx.should_requote(now){
+                    // we compute target laders and try to modify them 
+                    match ctx.compute_target_ladder(){
+                        Ok(mut target_ladder)=>{
+                           if let Ok(requote_result) = ctx.incremental_requote(&mut target_ladder , *symbol , now){
+                                let orders_to_cancel = requote_result.cancels;
+                                let orders_to_post = requote_result.posts;
+                                self.replace_batch.extend(requote_result.amends);
 
-                                    for order in orders_to_post{
-                                        self.post_bacth.push(PostData { price: order.price, qty: order.qty, side: order.side , symbol : *symbol , level : order.level });
-                                    }
-                               }
-                            }
+                                for order in orders_to_cancel {
+                                    self.cancel_batch.push(CancelData { symbol : deref_symbol , client_id: order.1, order_id: Some(order.0) });
+                                }
 
-                            Err(_)=>{
-                                eprint!("error occpured in the compute target ladder function ")
-                            }
+                                for order in orders_to_post{
+                                    self.post_bacth.push(PostData { price: order.price, qty: order.qty, side: order.side , symbol : *symbol , level : order.level });
+                                }
+                           }
+                        }
+
+                        Err(_)=>{
+                            eprint!("error occpured in the compute target ladder function ")
                         }
                     }
+                }
+
 
+                // shoudl i send requsts here 
 
-                    // shoudl i send requsts here 
 
 
+            }
+        }
+
+        // or shud i send requet here 
+        // cudnt call the function becuse it took a mutable refrence to entire self 
+        // both batches go out with one enqueue_batch each , a single head publish per batch
+        self.order_scratch.clear();
+        for cancel_order in &self.cancel_batch{
+            if let Some(id) = cancel_order.order_id {
+                self.order_scratch.push(MmOrder { 
+                    order_id : id, 
+                    client_id : cancel_order.client_id, 
+                    price: 0, 
+                    timestamp: 0, 
+                    shares_qty: 0, 
+                    symbol : cancel_order.symbol, 
+                    side: 2, 
+                    order_type: 1, 
+                    status: 4
+                });
+            }
+        }
+        let cancels_sent = match self.order_queue.send_batch(&self.order_scratch){
+            Ok(sent)=>{
+                if sent < self.order_scratch.len() {
+                    eprintln!(" enqueue erro , dropped {} cancels , order queue full" , self.order_scratch.len() - sent);
+                }
+                sent
+            }
+            Err(queue_error)=>{
+                eprintln!(" enqueue erro {:?}" , queue_error);
+                0
+            }
+        };
+        summary.cancels_sent = cancels_sent;
+        summary.cancels_dropped = self.order_scratch.len() - cancels_sent;
 
+        // amends go after the cancels and before the posts
+        self.order_scratch.clear();
+        if self.kill_switch.engaged {
+            self.replace_batch.clear();
+        }
+        // a price that does not fit the wire is never sent , the order keeps resting where it was
+        let mut unsendable = Vec::new();
+        self.replace_batch.retain(|replace_order| {
+            if wire_price(replace_order.price).is_some() {
+                return true;
+            }
+            eprintln!(" symbol {} amend of {} to {} skipped , price out of range" , replace_order.symbol , replace_order.client_id , replace_order.price);
+            unsendable.push(*replace_order);
+            false
+        });
+        for replace_order in &self.replace_batch{
+            self.order_scratch.push(MmOrder { 
+                order_id : replace_order.order_id, 
+                client_id : replace_order.client_id, 
+                price : wire_price(replace_order.price).unwrap_or_default(), 
+                timestamp: 0, 
+                shares_qty: replace_order.qty, 
+                symbol : replace_order.symbol, 
+                side: match replace_order.side {
+                    Side::ASK => 1 ,
+                    Side::BID => 0 
+                }, 
+                order_type: 4, 
+                status: 0
+            });
+        }
+        let amends_sent = match self.order_queue.send_batch(&self.order_scratch){
+            Ok(sent)=>{
+                if sent < self.order_scratch.len() {
+                    eprintln!(" enqueue erro , dropped {} amends , order queue full" , self.order_scratch.len() - sent);
+                }
+                sent
+            }
+            Err(queue_error)=>{
+                eprintln!(" enqueue erro {:?}" , queue_error);
+                0
+            }
+        };
+        // a dropped or skipped amend never reached the engine , the order still rests with its old price and size
+        for replace_order in self.replace_batch[amends_sent..].iter().chain(&unsendable){
+            if let Some(ctx) = self.symbol_ctx.get_mut(&replace_order.symbol) {
+                let left = ctx.orders.update(replace_order.client_id, |order| {
+                    order.revert_replace();
+                    order.remaining_size
+                });
+                if left == Some(0) {
+                    ctx.orders.remove(replace_order.client_id);
                 }
             }
+        }
+        summary.amends_sent = amends_sent;
+        summary.amends_dropped = self.order_scratch.len() - amends_sent + unsendable.len();
 
-            // or shud i send requet here 
-            // cudnt call the function becuse it took a mutable refrence to entire self 
-            for cancel_order in &mut  self.cancel_batch{
-                match cancel_order.order_id{
-                    Some(id)=>{
-                        match self.order_queue.enqueue(MmOrder { 
-                            order_id : id, 
-                            client_id : cancel_order.client_id, 
-                            price: 0, 
-                            timestamp: 0, 
-                            shares_qty: 0, 
-                            symbol : cancel_order.symbol, 
-                            side: 2, 
-                            order_type: 1, 
-                            status: 4
-                        }){
-                            Ok(_)=>{
-                
-                            }
-                            Err(queue_error)=>{
-                                eprintln!(" enqueue erro {:?}" , queue_error);
-                            }
-                        }
-                    }
-                    None =>{
 
-                    }
+        // client ids are allocated up front , the pending order is only recorded once its post made it into the queue
+        self.order_scratch.clear();
+        if self.kill_switch.engaged {
+            // nothing new goes out once the kill switch is pulled
+            self.post_bacth.clear();
+        }
+        self.post_bacth.retain(|post_order| self.symbol_ctx.contains_key(&post_order.symbol));
+        // same as the amends , a post whose price does not fit the wire is never sent or recorded
+        let before = self.post_bacth.len();
+        self.post_bacth.retain(|post_order| {
+            if wire_price(post_order.price).is_some() {
+                return true;
+            }
+            eprintln!(" symbol {} post at {} skipped , price out of range" , post_order.symbol , post_order.price);
+            false
+        });
+        let unsendable = before - self.post_bacth.len();
+        for post_order in &self.post_bacth{
+            if let Some(ctx) = self.symbol_ctx.get_mut(&post_order.symbol) {
+                let client_id =  ctx.orders.alloc_client_id();
+                self.order_scratch.push(MmOrder { 
+                    order_id : 0 , 
+                    client_id  , 
+                    price : wire_price(post_order.price).unwrap_or_default(), 
+                    timestamp: 0, 
+                    shares_qty: post_order.qty, 
+                    symbol : post_order.symbol, 
+                    side: match post_order.side {
+                        Side::ASK => 1 ,
+                        Side::BID => 0 
+                    }, 
+                    order_type: 0, 
+                    status: 0
+                });
+            }
+        }
+        let posted = match self.order_queue.send_batch(&self.order_scratch){
+            Ok(sent)=>{
+                if sent < self.order_scratch.len() {
+                    eprintln!(" enqueue erro , dropped {} posts , order queue full" , self.order_scratch.len() - sent);
                 }
+                sent
             }
+            Err(queue_error)=>{
+                eprintln!(" enqueue erro {:?}" , queue_error);
+                0
+            }
+        };
+        for (order, post_order) in self.order_scratch[..posted].iter().zip(&self.post_bacth){
+            if let Some(ctx) = self.symbol_ctx.get_mut(&post_order.symbol) {
+                // push it to the order manager 
+                ctx.orders.insert(PendingOrder { 
+                    client_id : order.client_id, 
+                    exchange_order_id: None, 
+                    side: post_order.side, 
+                    price: post_order.price, 
+                    original_size: post_order.qty, 
+                    remaining_size: post_order.qty, 
+                    state: OrderState::PendingNew, 
+                    level: post_order.level, 
+                    created_at: now ,
+                    cancel_on_ack: false ,
+                    cancel_sent_at: 0 ,
+                    cancel_retries: 0 ,
+                    stuck_since: None ,
+                    replaced_from: None
+                });
+            }
+        }
+        summary.posted = posted;
+        summary.posts_dropped = self.order_scratch.len() - posted + unsendable;
+
+        self.cancel_batch.clear();
+        self.replace_batch.clear();
+        self.post_bacth.clear();
+        self.check_kill_completion(&mut summary);
+        summary
+    }
 
+    // market maker running looop
+    // runs until SIGINT or SIGTERM (main installs the handlers) , then gets out of the book and reports
+
+    pub fn run_market_maker(&mut self)->ShutdownReport{
+        // backs off when a whole pass finds nothing to do , so an idle bot does not pin a core
+        let mut idle = Backoff::new(self.idle_wait);
+        while !shutdown_requested() {
+            if self.tick(self.clock.now_nanos()).is_idle() {
+                idle.snooze();
+            } else {
+                idle.reset();
+            }
+        }
+        self.shutdown()
+    }
 
-            for post_order in &mut self.post_bacth{
-                match self.symbol_ctx.get_mut(&post_order.symbol){
-                    Some(ctx)=>{
-                        let client_id =  ctx.orders.alloc_client_id();
-                        match self.order_queue.enqueue(MmOrder { 
-                            order_id : 0 , 
-                            client_id  , 
-                            price : post_order.price.to_u64().unwrap(), 
-                            timestamp: 0, 
-                            shares_qty: post_order.qty, 
-                            symbol : post_order.symbol, 
-                            side: match post_order.side {
-                                Side::ASK => 1 ,
-                                Side::BID => 0 
-                            }, 
-                            order_type: 0, 
-                            status: 0
-                        }){
-                            Ok(_)=>{
-                                // push it to the order manager 
-                                ctx.orders.pending_orders.push(PendingOrder { 
-                                    client_id, 
-                                    exchange_order_id: None, 
-                                    side: post_order.side, 
-                                    price: post_order.price, 
-                                    original_size: post_order.qty, 
-                                    remaining_size: post_order.qty, 
-                                    state: OrderState::PendingNew, 
-                                    level: post_order.level, 
-                                    created_at: Instant::now() 
-                                });
-                            }
-                            Err(queue_error)=>{
-                                eprintln!(" enqueue erro {:?}" , queue_error);
-                            }
+    /// Pulls the kill switch and keeps ticking until every cancel ack and last fill is in ,
+    /// `config.shutdown_timeout` runs out or another SIGINT / SIGTERM arrives.
+    /// The time comes from `self.clock` , with a SimClock it has to be advanced from another thread.
+    pub fn shutdown(&mut self)->ShutdownReport{
+        // keeps the original reason if it was pulled already
+        self.kill_switch.trigger.trigger(KillReason::Shutdown);
+        let started = self.clock.now_nanos();
+        let mut idle = Backoff::new(self.idle_wait);
+        let mut total = TickSummary::default();
+        let timed_out = loop {
+            let now = self.clock.now_nanos();
+            let summary = self.tick(now);
+            total.fills += summary.fills;
+            total.cancels_sent += summary.cancels_sent;
+            total.cancels_dropped += summary.cancels_dropped;
+            if self.kill_switch.completed {
+                break false;
+            }
+            if elapsed(now, started) >= self.config.shutdown_timeout || shutdown_requested() {
+                break true;
+            }
+            if summary.is_idle() {
+                idle.snooze();
+            } else {
+                idle.reset();
+            }
+        };
+        let waited = elapsed(self.clock.now_nanos(), started);
+
+        let mut symbols : Vec<SymbolReport> = self.symbol_ctx.iter().map(|(symbol , ctx)| SymbolReport {
+            symbol : *symbol,
+            mode : format!("{:?}" , ctx.state.current_mode),
+            inventory : ctx.state.inventory.quantity,
+            avg_entry_price : ctx.state.inventory.avg_entry_price,
+            realized_pnl : ctx.state.pnl.realized,
+            unrealized_pnl : ctx.state.pnl.unrealized,
+            total_pnl : ctx.state.pnl.total,
+            orders_left : ctx.orders.len(),
+            ghosts : ctx.orders.ghosts.len(),
+            fill_gap_detected : ctx.state.fill_gap_detected,
+            rejects : ctx.orders.rejects.total(),
+            quarantined : ctx.state.quarantined,
+        }).collect();
+        symbols.sort_by_key(|report| report.symbol);
+
+        ShutdownReport {
+            clean : symbols.iter().all(|report| report.orders_left == 0 && report.ghosts == 0),
+            timed_out,
+            waited_ms : waited.as_millis() as u64,
+            cancels_sent : total.cancels_sent,
+            cancels_dropped : total.cancels_dropped,
+            fills : total.fills,
+            total_pnl : symbols.iter().map(|report| report.total_pnl).sum(),
+            symbols,
+        }
+    }
+}
+
+// the engine takes whole non negative prices , anything else is never sent
+fn wire_price(price : Decimal)->Option<u64>{
+    if price.fract().is_zero() { price.to_u64() } else { None }
+}
+
+// both are taken so one signal is not left over for the wait in shutdown
+fn shutdown_requested()->bool{
+    signals::take(libc::SIGINT) | signals::take(libc::SIGTERM)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::mmbot::clock::SimClock;
+    use crate::mmbot::shutdown::{EXIT_CLEAN, EXIT_ORDERS_LEFT};
+    use crate::transport::memory::MemQueue;
+
+    // an order resting on the simulated engine
+    #[derive(Debug, Clone, Copy)]
+    struct Resting {
+        order_id : u64 ,
+        client_id : u64 ,
+        price : u64 ,
+        qty : u32 ,
+        side : u8 ,
+        symbol : u32 ,
+    }
+
+    // the bot on in process queues and a simulated clock , the test plays the engine
+    struct Harness {
+        mm : MarketMaker<MemQueue<MarketMakerFeed> , MemQueue<MarketMakerFill> , MemQueue<MessageFromApi> , MemQueue<MmOrder> , SimClock> ,
+        feeds : MemQueue<MarketMakerFeed> ,
+        fills : MemQueue<MarketMakerFill> ,
+        api : MemQueue<MessageFromApi> ,
+        orders : MemQueue<MmOrder> ,
+        clock : SimClock ,
+        resting : Vec<Resting> ,
+        next_order_id : u64 ,
+        fill_seq : FxHashMap<u32 , u64> ,
+        // everything the bot sent , in order
+        sent : Vec<MmOrder> ,
+        // order types the engine swallows without an answer
+        deaf_to : Vec<u8> ,
+    }
+
+    impl Harness {
+        // a wide tick keeps the bootstrap ladder within the cancel distances of the test feed
+        fn new()->Self{
+            let mut config = MmConfig::default();
+            config.defaults.tick_size = Decimal::from(5);
+            Self::with_config(config)
+        }
+
+        fn with_config(config : MmConfig)->Self{
+            let feeds = MemQueue::new(1 << 16);
+            let fills = MemQueue::new(1 << 16);
+            let api = MemQueue::new(1 << 16);
+            let orders = MemQueue::new(1 << 16);
+            let clock = SimClock::new(1_000_000_000);
+            let config = MmConfig { epoch_file : None , ..config };
+            let mm = MarketMaker::with_transport(feeds.clone(), fills.clone(), api.clone(), orders.clone(), clock.clone()).with_config(config).with_epoch(1);
+            Self { mm , feeds , fills , api , orders , clock , resting : Vec::new() , next_order_id : 1 , fill_seq : FxHashMap::default() , sent : Vec::new() , deaf_to : Vec::new() }
+        }
+
+        fn tick(&mut self)->TickSummary{
+            self.clock.advance(Duration::from_millis(1));
+            let now = self.clock.now_nanos();
+            self.mm.tick(now)
+        }
+
+        // one tick `by` after the last one , then the engine answers whatever the bot sent
+        fn step(&mut self , by : Duration)->TickSummary{
+            self.clock.advance(by);
+            let now = self.clock.now_nanos();
+            let summary = self.mm.tick(now);
+            self.engine();
+            summary
+        }
+
+        fn add_symbol(&mut self , symbol : u32 , ipo_price : u64){
+            self.api.push(MessageFromApi { symbol , ipo_price , message_type : 0 , ..Default::default() }).unwrap();
+        }
+
+        fn feed(&mut self , symbol : u32 , seq : u64 , best_bid : u64 , best_ask : u64){
+            self.feeds.push(MarketMakerFeed { seq , symbol , best_bid , best_ask , best_bid_qty : 100 , best_ask_qty : 100 , last_traded_price : (best_bid + best_ask) / 2 , ..Default::default() }).unwrap();
+        }
+
+        // fills the resting order and publishes it with the next fill seq of its symbol
+        fn fill(&mut self , order_id : u64 , qty : u32){
+            let fill = self.fill_later(order_id, qty);
+            self.fills.push(fill).unwrap();
+        }
+
+        // fills the resting order on the engine , the record is handed back for the test to publish when it wants
+        fn fill_later(&mut self , order_id : u64 , qty : u32)->MarketMakerFill{
+            let position = self.resting.iter().position(|order| order.order_id == order_id).unwrap();
+            let order = self.resting[position];
+            let seq = self.fill_seq.entry(order.symbol).or_default();
+            *seq += 1;
+            let fill = MarketMakerFill { order_id_mm_order : order_id , seq : *seq , fill_price : order.price , fill_quantity : qty , symbol : order.symbol , side_of_mm_order : order.side , ..Default::default() };
+            self.resting[position].qty -= qty;
+            if self.resting[position].qty == 0 {
+                self.resting.remove(position);
+            }
+            fill
+        }
+
+        // steps until `n` more reconciles of the symbol have finished
+        fn reconciles(&mut self , symbol : u32 , n : u64 , feed_seq : &mut u64){
+            let done = self.mm.symbol_ctx[&symbol].orders.reconciler.stats.completed + n;
+            for _ in 0..1000 {
+                *feed_seq += 1;
+                self.feed(symbol, *feed_seq, 950, 1050);
+                self.step(Duration::from_millis(100));
+                if self.mm.symbol_ctx[&symbol].orders.reconciler.stats.completed >= done {
+                    return;
+                }
+            }
+            panic!("symbol {} did not reconcile" , symbol);
+        }
+
+        // accepts every post , cancel , replace and open orders query the bot sent
+        fn engine(&mut self){
+            for order in self.orders.drain() {
+                self.sent.push(order);
+                if self.deaf_to.contains(&order.order_type) {
+                    continue;
+                }
+                match order.order_type {
+                    0 => {
+                        let order_id = self.next_order_id;
+                        self.next_order_id += 1;
+                        self.resting.push(Resting { order_id , client_id : order.client_id , price : order.price , qty : order.shares_qty , side : order.side , symbol : order.symbol });
+                        self.api.push(MessageFromApi { order_id , client_id : order.client_id , symbol : order.symbol , message_type : 1 , ..Default::default() }).unwrap();
+                    }
+                    1 => {
+                        self.resting.retain(|resting| resting.order_id != order.order_id);
+                        self.api.push(MessageFromApi { order_id : order.order_id , client_id : order.client_id , symbol : order.symbol , message_type : 2 , ..Default::default() }).unwrap();
+                    }
+                    3 => {
+                        let mine : Vec<Resting> = self.resting.iter().filter(|resting| resting.symbol == order.symbol).copied().collect();
+                        for resting in &mine {
+                            self.api.push(MessageFromApi { order_id : resting.order_id , client_id : resting.client_id , ipo_price : resting.price , shares_qty : resting.qty , symbol : resting.symbol , side : resting.side , message_type : 6 , ..Default::default() }).unwrap();
                         }
+                        self.api.push(MessageFromApi { order_id : order.order_id , shares_qty : mine.len() as u32 , symbol : order.symbol , message_type : 7 , ..Default::default() }).unwrap();
                     }
-                    None =>{
-        
+                    4 => {
+                        if let Some(resting) = self.resting.iter_mut().find(|resting| resting.order_id == order.order_id) {
+                            resting.price = order.price;
+                            resting.qty = order.shares_qty;
+                        }
+                        self.api.push(MessageFromApi { order_id : order.order_id , client_id : order.client_id , shares_qty : order.shares_qty , symbol : order.symbol , message_type : 8 , ..Default::default() }).unwrap();
                     }
+                    _ => {}
                 }
             }
+        }
 
+        fn resting_on(&self , symbol : u32)->usize{
+            self.resting.iter().filter(|resting| resting.symbol == symbol).count()
+        }
+    }
 
+    #[test]
+    fn full_feed_batch_does_not_drop_the_record_left_behind(){
+        let mut harness = Harness::new();
+        for symbol in 1..=300 {
+            harness.add_symbol(symbol, 1000);
+        }
+        harness.tick();
 
+        // two gap free records per symbol , interleaved so batches end in the middle of a round
+        for seq in 1..=2 {
+            for symbol in 1..=300 {
+                harness.feeds.push(MarketMakerFeed { seq , symbol , best_bid : 995 , best_ask : 1005 , best_bid_qty : 100 , best_ask_qty : 100 , last_traded_price : 1000 , ..Default::default() }).unwrap();
+            }
+        }
+        let summary = harness.tick();
 
+        assert_eq!(summary.feeds, 600);
+        for ctx in harness.mm.symbol_ctx.values() {
+            assert_eq!(ctx.state.feed_seq.stale, 0);
+            assert_eq!(ctx.state.feed_seq.gaps, 0);
+            assert_eq!(ctx.state.feed_seq.last_seq, 2);
+        }
+    }
 
+    // quotes the symbol until the engine has some of its orders resting and the bot has their acks
+    fn quote(harness : &mut Harness , symbol : u32 , feed_seq : &mut u64){
+        for _ in 0..200 {
+            *feed_seq += 1;
+            harness.feed(symbol, *feed_seq, 950, 1050);
+            harness.step(Duration::from_millis(100));
+            if harness.resting_on(symbol) > 0 {
+                harness.tick();
+                return;
+            }
         }
+        panic!("symbol {} never quoted" , symbol);
+    }
+
+    #[test]
+    fn post_ack_cancel_ack_round_trip(){
+        let mut harness = Harness::new();
+        let mut feed_seq = 0;
+        harness.add_symbol(1, 1000);
+
+        // the first ticks bootstrap the symbol , the posts go out once it is quoting
+        let mut posted = 0;
+        for _ in 0..200 {
+            feed_seq += 1;
+            harness.feed(1, feed_seq, 950, 1050);
+            harness.clock.advance(Duration::from_millis(100));
+            let now = harness.clock.now_nanos();
+            posted = harness.mm.tick(now).posted;
+            if posted > 0 {
+                break;
+            }
+            harness.engine();
+        }
+        assert!(posted > 0);
+        let ctx = &harness.mm.symbol_ctx[&1];
+        assert_eq!(ctx.orders.len(), posted);
+        assert!(ctx.orders.iter().all(|order| order.state == OrderState::PendingNew && order.exchange_order_id.is_none()));
+
+        // the engine acks every post
+        harness.engine();
+        harness.tick();
+        let ctx = &harness.mm.symbol_ctx[&1];
+        assert_eq!(harness.resting_on(1), posted);
+        for resting in &harness.resting {
+            let order = ctx.orders.get(resting.client_id).unwrap();
+            assert_eq!(order.state, OrderState::Active);
+            assert_eq!(order.exchange_order_id, Some(resting.order_id));
+        }
+
+        // cancels go out with the next tick , the orders stay until their ack
+        assert_eq!(harness.mm.cancel_all_orders(1).unwrap(), posted);
+        let summary = harness.tick();
+        assert_eq!(summary.cancels_sent, posted);
+        assert!(harness.mm.symbol_ctx[&1].orders.iter().all(|order| order.state == OrderState::PendingCancel));
+
+        harness.engine();
+        harness.tick();
+        assert_eq!(harness.resting_on(1), 0);
+        assert!(harness.mm.symbol_ctx[&1].orders.is_empty());
     }
-}
 
+    #[test]
+    fn kill_switch_completes_once_every_cancel_is_acked(){
+        let mut harness = Harness::new();
+        let mut feed_seq = 0;
+        harness.add_symbol(1, 1000);
+        harness.add_symbol(2, 1000);
+        quote(&mut harness, 1, &mut feed_seq);
+        quote(&mut harness, 2, &mut feed_seq);
+
+        harness.mm.kill_trigger().trigger(KillReason::Manual);
+        let summary = harness.tick();
+        assert_eq!(summary.kill_engaged, Some(KillReason::Manual));
+        assert!(!summary.kill_completed);
+        assert!(summary.cancels_sent > 0);
+
+        // nothing new goes out while the acks are on their way
+        harness.engine();
+        let summary = harness.tick();
+        assert!(summary.kill_completed);
+        assert_eq!(summary.posted, 0);
+        assert!(harness.mm.kill_switch.completed);
+        assert_eq!(harness.resting_on(1) + harness.resting_on(2), 0);
+        assert!(harness.mm.symbol_ctx.values().all(|ctx| ctx.orders.is_empty() && ctx.state.current_mode == QuotingMode::Emergency));
+
+        feed_seq += 1;
+        harness.feed(1, feed_seq, 950, 1050);
+        let summary = harness.step(Duration::from_millis(100));
+        assert_eq!(summary.posted, 0);
+        assert!(!summary.kill_completed);
+    }
+
+    #[test]
+    fn shutdown_after_a_completed_kill_exits_clean(){
+        let mut harness = Harness::new();
+        let mut feed_seq = 0;
+        harness.add_symbol(1, 1000);
+        quote(&mut harness, 1, &mut feed_seq);
+
+        harness.mm.kill_trigger().trigger(KillReason::Manual);
+        harness.step(Duration::from_millis(1));
+        harness.tick();
+        assert!(harness.mm.kill_switch.completed);
+
+        let report = harness.mm.shutdown();
+        assert!(report.clean);
+        assert!(!report.timed_out);
+        assert_eq!(report.symbols[0].orders_left, 0);
+        assert_eq!(report.exit_code(), EXIT_CLEAN);
+    }
+
+    #[test]
+    fn shutdown_with_unacked_cancels_exits_with_orders_left(){
+        let mut config = MmConfig { shutdown_timeout : Duration::ZERO , ..MmConfig::default() };
+        config.defaults.tick_size = Decimal::from(5);
+        let mut harness = Harness::with_config(config);
+        let mut feed_seq = 0;
+        harness.add_symbol(1, 1000);
+        quote(&mut harness, 1, &mut feed_seq);
+        let resting = harness.resting_on(1);
+
+        // the cancels are sent but the wait is over before any ack
+        let report = harness.mm.shutdown();
+        assert!(!report.clean);
+        assert!(report.timed_out);
+        assert_eq!(report.cancels_sent, resting);
+        assert_eq!(report.symbols[0].orders_left, resting);
+        assert_eq!(report.exit_code(), EXIT_ORDERS_LEFT);
+    }
+
+    #[test]
+    fn acks_for_a_symbol_we_dont_have_are_counted_not_fatal(){
+        let mut harness = Harness::new();
+        harness.add_symbol(1, 1000);
+        harness.tick();
+
+        // a previous run's order on a symbol this run has not added (yet)
+        let old_client_id = ClientId { epoch : 7 , symbol : 9 , seq : 1 }.encode();
+        harness.api.push(MessageFromApi { order_id : 90 , client_id : old_client_id , symbol : 9 , message_type : 1 , ..Default::default() }).unwrap();
+        harness.api.push(MessageFromApi { order_id : 90 , client_id : old_client_id , symbol : 9 , message_type : 2 , ..Default::default() }).unwrap();
+        harness.api.push(MessageFromApi { order_id : 91 , symbol : 9 , message_type : 8 , ..Default::default() }).unwrap();
+        let summary = harness.tick();
+
+        assert_eq!(summary.api_messages, 3);
+        assert_eq!(summary.unknown_acks, 3);
+        assert!(harness.mm.symbol_ctx.contains_key(&1));
+    }
 
+    #[test]
+    fn symbol_too_wide_for_a_client_id_is_rejected(){
+        let mut harness = Harness::new();
+        harness.add_symbol(MAX_CLIENT_ID_SYMBOL, 1000);
+        harness.add_symbol(MAX_CLIENT_ID_SYMBOL + 1, 1000);
+        let summary = harness.tick();
+
+        assert_eq!(summary.symbols_rejected, 1);
+        assert!(harness.mm.symbol_ctx.contains_key(&MAX_CLIENT_ID_SYMBOL));
+        assert!(!harness.mm.symbol_ctx.contains_key(&(MAX_CLIENT_ID_SYMBOL + 1)));
+    }
+
+    #[test]
+    fn fill_gap_is_cleared_by_a_clean_reconcile(){
+        let mut harness = Harness::new();
+        let mut feed_seq = 0;
+        harness.add_symbol(1, 1000);
+        quote(&mut harness, 1, &mut feed_seq);
+
+        let order_id = harness.resting[0].order_id;
+        harness.fill(order_id, 1);
+        // seq 2 never shows up
+        *harness.fill_seq.get_mut(&1).unwrap() += 1;
+        let order_id = harness.resting[0].order_id;
+        harness.fill(order_id, 1);
+        harness.step(Duration::from_millis(1));
+        assert!(harness.mm.symbol_ctx[&1].state.fill_gap_detected);
+        assert_eq!(harness.mm.symbol_ctx[&1].state.current_mode, QuotingMode::Emergency);
+
+        for _ in 0..300 {
+            feed_seq += 1;
+            harness.feed(1, feed_seq, 950, 1050);
+            harness.step(Duration::from_millis(100));
+            if !harness.mm.symbol_ctx[&1].state.fill_gap_detected {
+                return;
+            }
+        }
+        panic!("the fill gap was never cleared");
+    }
 
+    #[test]
+    fn fill_arriving_after_the_snapshot_is_not_counted_twice(){
+        let mut harness = Harness::new();
+        let mut feed_seq = 0;
+        harness.add_symbol(1, 1000);
+        quote(&mut harness, 1, &mut feed_seq);
+
+        let resting = harness.resting[0];
+        // the engine fills before the snapshot , the fill record only lands after the end marker
+        let fill = harness.fill_later(resting.order_id, 5);
+        harness.reconciles(1, 1, &mut feed_seq);
+        harness.fills.push(fill).unwrap();
+        harness.step(Duration::from_millis(1));
+        harness.reconciles(1, 1, &mut feed_seq);
+
+        let ctx = &harness.mm.symbol_ctx[&1];
+        let order = ctx.orders.get(resting.client_id).unwrap();
+        assert_eq!(order.remaining_size, resting.qty - 5);
+        assert!(!ctx.state.fill_gap_detected);
+        assert_eq!(ctx.orders.reconciler.stats.discrepancies, 0);
+    }
+
+    #[test]
+    fn fill_missing_for_two_snapshots_is_a_gap(){
+        let mut harness = Harness::new();
+        let mut feed_seq = 0;
+        harness.add_symbol(1, 1000);
+        quote(&mut harness, 1, &mut feed_seq);
+
+        let resting = harness.resting[0];
+        // never published
+        harness.fill_later(resting.order_id, 5);
+        harness.reconciles(1, 1, &mut feed_seq);
+        assert!(!harness.mm.symbol_ctx[&1].state.fill_gap_detected);
+        harness.reconciles(1, 1, &mut feed_seq);
+
+        let ctx = &harness.mm.symbol_ctx[&1];
+        assert!(ctx.state.fill_gap_detected);
+        assert_eq!(ctx.orders.get(resting.client_id).unwrap().remaining_size, resting.qty - 5);
+    }
+
+    // puts an amend in flight for the first resting order , as incremental_requote would
+    fn amend_first(harness : &mut Harness , qty : u32)->Resting{
+        let resting = harness.resting[0];
+        let now = harness.clock.now_nanos();
+        harness.mm.symbol_ctx.get_mut(&resting.symbol).unwrap().orders.update(resting.client_id, |order| {
+            let price = order.price + Decimal::from(5);
+            order.mark_pending_replace(price, qty, now);
+        }).unwrap();
+        resting
+    }
+
+    #[test]
+    fn fill_during_a_rejected_amend_stays_off_the_order(){
+        let mut harness = Harness::new();
+        let mut feed_seq = 0;
+        harness.add_symbol(1, 1000);
+        quote(&mut harness, 1, &mut feed_seq);
+
+        let qty = harness.resting[0].qty + 10;
+        let resting = amend_first(&mut harness, qty);
+        harness.fill(resting.order_id, 5);
+        harness.api.push(MessageFromApi { order_id : resting.order_id , client_id : resting.client_id , symbol : 1 , message_type : 5 , reject_reason : RejectReason::PriceOutOfBand as u8 , ..Default::default() }).unwrap();
+        harness.tick();
+
+        let order = harness.mm.symbol_ctx[&1].orders.get(resting.client_id).copied().unwrap();
+        assert_eq!(order.state, OrderState::PartiallyFilled);
+        assert_eq!(order.original_size, resting.qty);
+        assert_eq!(order.remaining_size, resting.qty - 5);
+    }
+
+    #[test]
+    fn fill_larger_than_the_amended_qty_waits_for_the_ack(){
+        let mut harness = Harness::new();
+        let mut feed_seq = 0;
+        harness.add_symbol(1, 1000);
+        quote(&mut harness, 1, &mut feed_seq);
+
+        let resting = amend_first(&mut harness, 3);
+        harness.fill(resting.order_id, 5);
+        harness.tick();
+        let order = harness.mm.symbol_ctx[&1].orders.get(resting.client_id).copied().unwrap();
+        assert_eq!(order.state, OrderState::PendingReplace);
+        assert_eq!(order.remaining_size, 0);
+
+        // the engine moved what was left of the order
+        harness.api.push(MessageFromApi { order_id : resting.order_id , client_id : resting.client_id , shares_qty : 3 , symbol : 1 , message_type : 8 , ..Default::default() }).unwrap();
+        harness.tick();
+        let order = harness.mm.symbol_ctx[&1].orders.get(resting.client_id).copied().unwrap();
+        assert_eq!(order.state, OrderState::Active);
+        assert_eq!(order.remaining_size, 3);
+    }
+
+    #[test]
+    fn amend_to_a_negative_price_is_skipped_and_reverted(){
+        let mut harness = Harness::new();
+        let mut feed_seq = 0;
+        harness.add_symbol(1, 1000);
+        quote(&mut harness, 1, &mut feed_seq);
+
+        let resting = harness.resting[0];
+        let now = harness.clock.now_nanos();
+        let (price, side) = harness.mm.symbol_ctx.get_mut(&1).unwrap().orders.update(resting.client_id, |order| {
+            let old = (order.price, order.side);
+            order.mark_pending_replace(Decimal::from(-5), resting.qty, now);
+            old
+        }).unwrap();
+        harness.mm.replace_batch.push(ReplaceData { symbol : 1 , client_id : resting.client_id , order_id : resting.order_id , price : Decimal::from(-5) , qty : resting.qty , side });
+        let summary = harness.tick();
+        harness.engine();
+
+        assert_eq!(summary.amends_sent, 0);
+        assert_eq!(summary.amends_dropped, 1);
+        assert!(harness.sent.iter().all(|order| order.order_type != 4));
+        let order = harness.mm.symbol_ctx[&1].orders.get(resting.client_id).copied().unwrap();
+        assert_eq!(order.state, OrderState::Active);
+        assert_eq!(order.price, price);
+    }
+
+    #[test]
+    fn post_at_a_negative_or_fractional_price_is_skipped(){
+        let mut harness = Harness::new();
+        let mut feed_seq = 0;
+        harness.add_symbol(1, 1000);
+        quote(&mut harness, 1, &mut feed_seq);
+        harness.engine();
+        let resting = harness.mm.symbol_ctx[&1].orders.len();
+        let sent = harness.sent.len();
+
+        harness.mm.post_bacth.push(PostData { symbol : 1 , price : Decimal::from(-5) , qty : 10 , side : Side::BID , level : 0 });
+        harness.mm.post_bacth.push(PostData { symbol : 1 , price : Decimal::new(9505, 1) , qty : 10 , side : Side::BID , level : 1 });
+        let summary = harness.tick();
+        harness.engine();
+
+        assert_eq!(summary.posted, 0);
+        assert_eq!(summary.posts_dropped, 2);
+        assert!(harness.sent[sent..].iter().all(|order| order.order_type != 0));
+        let ctx = &harness.mm.symbol_ctx[&1];
+        assert_eq!(ctx.orders.len(), resting);
+        assert!(ctx.orders.iter().all(|order| order.state != OrderState::PendingNew));
+    }
+
+    #[test]
+    fn unanswered_amend_is_cancelled_by_its_exchange_id(){
+        let mut harness = Harness::new();
+        let mut feed_seq = 0;
+        harness.add_symbol(1, 1000);
+        quote(&mut harness, 1, &mut feed_seq);
+
+        // the amend went out and nothing came back
+        let resting = amend_first(&mut harness, 50);
+        for _ in 0..50 {
+            feed_seq += 1;
+            harness.feed(1, feed_seq, 950, 1050);
+            harness.step(Duration::from_millis(100));
+        }
+
+        assert!(harness.sent.iter().any(|order| order.order_type == 1 && order.order_id == resting.order_id));
+        assert!(harness.resting.iter().all(|order| order.order_id != resting.order_id));
+        assert!(harness.mm.symbol_ctx[&1].orders.get(resting.client_id).is_none());
+        assert!(harness.mm.symbol_ctx[&1].orders.ghosts.is_empty());
+    }
+
+    #[test]
+    fn ghosts_hold_the_kill_switch_until_the_engine_shows_them_gone(){
+        let mut harness = Harness::new();
+        let mut feed_seq = 0;
+        harness.add_symbol(1, 1000);
+        quote(&mut harness, 1, &mut feed_seq);
+        let resting = harness.resting_on(1);
+
+        // cancels go unanswered , every order ends up a ghost
+        harness.deaf_to.push(1);
+        harness.mm.kill_trigger().trigger(KillReason::Manual);
+        for _ in 0..600 {
+            feed_seq += 1;
+            harness.feed(1, feed_seq, 950, 1050);
+            harness.step(Duration::from_millis(100));
+        }
+        let ctx = &harness.mm.symbol_ctx[&1];
+        assert!(ctx.orders.is_empty());
+        assert_eq!(ctx.orders.ghosts.len(), resting);
+        assert_eq!(harness.resting_on(1), resting);
+        assert!(!harness.mm.kill_switch.completed);
+        // resent once per cancel timeout and with every snapshot , not on every tick
+        let config = &harness.mm.config;
+        let waited = Duration::from_millis(100 * 600);
+        let bound = (waited.as_millis() / config.ack_timeouts.cancel.as_millis() + waited.as_millis() / config.reconcile_gap.as_millis()) as usize
+            + config.ack_timeouts.max_cancel_retries as usize + 2;
+        for order in harness.resting.iter().filter(|order| order.symbol == 1) {
+            let cancels = harness.sent.iter().filter(|sent| sent.order_type == 1 && sent.order_id == order.order_id).count();
+            assert!(cancels >= 2 && cancels <= bound, "order {} got {} cancels , bound {}" , order.order_id , cancels , bound);
+        }
+
+        // the engine answers again , the next snapshot lists the ghosts and they are cancelled
+        harness.deaf_to.clear();
+        for _ in 0..200 {
+            feed_seq += 1;
+            harness.feed(1, feed_seq, 950, 1050);
+            harness.step(Duration::from_millis(100));
+            if harness.mm.kill_switch.completed {
+                break;
+            }
+        }
+        assert!(harness.mm.kill_switch.completed);
+        assert_eq!(harness.resting_on(1), 0);
+        assert!(harness.mm.symbol_ctx[&1].orders.ghosts.is_empty());
+    }
+}
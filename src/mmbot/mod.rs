@@ -1,4 +1,11 @@
 pub mod market_maker;
 pub mod rolling_price;
 pub mod types;
-pub mod constants;
\ No newline at end of file
+pub mod constants;
+pub mod clock;
+pub mod config;
+pub mod signals;
+pub mod kill_switch;
+pub mod shutdown;
+pub mod reconcile;
+pub mod client_id;
\ No newline at end of file
@@ -0,0 +1,347 @@
+use std::time::Duration;
+
+use rust_decimal::prelude::ToPrimitive;
+use rustc_hash::FxHashMap;
+
+use crate::mmbot::clock::elapsed;
+use crate::mmbot::types::{CancelData, OrderState, Side, SymbolOrders};
+use crate::shm::response_queue_mm::MessageFromApi;
+
+// periodic check of our order book against the engine's
+// MmOrder order_type 3 asks for our open orders on a symbol , the engine answers with one api message 6
+// per resting order and a 7 to close the snapshot , on the 7 the two views are diffed and ours is fixed
+//
+// the snapshot is taken somewhere between our query and its end marker , so anything that changed
+// locally in that window can look like a discrepancy : orders posted after the query are left out of the
+// diff , an order we already saw cancelled but the engine still lists just gets a harmless second cancel
+// fills travel on their own queue and can land after the end marker , so an order the engine has less of
+// than we do is only a lost fill once the next snapshot finds our size still above the one it saw before
+
+/// One resting order as the engine reported it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnapshotEntry {
+    pub order_id: u64,
+    pub client_id: u64,
+    pub price: u64,
+    pub qty: u32,
+    pub side: Side,
+}
+
+impl SnapshotEntry {
+    pub fn from_message(message: &MessageFromApi) -> Self {
+        Self {
+            order_id: message.order_id,
+            client_id: message.client_id,
+            price: message.ipo_price,
+            qty: message.shares_qty,
+            side: if message.side == 1 { Side::ASK } else { Side::BID },
+        }
+    }
+}
+
+/// What the diff found and fixed , every one is logged.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Discrepancy {
+    // the engine has it , we were still waiting for the accept ack
+    AckMissed { client_id: u64, order_id: u64 },
+    // the engine already moved it , we were still waiting for the replace ack
+    ReplaceAckMissed { client_id: u64, order_id: u64 },
+    // the engine still has it at the old price , the amend we sent before the query got lost
+    ReplaceLost { client_id: u64 },
+    // we posted it before the query but the engine never got it
+    NeverReachedEngine { client_id: u64 },
+    // we think it rests , the engine does not have it (filled or cancelled without us hearing)
+    GoneOnEngine { client_id: u64, order_id: u64 },
+    // rests on the engine , we dont track it , cancelled
+    UnknownOnEngine { client_id: u64, order_id: u64 },
+    // the last snapshot had less left than we still think , a fill went missing
+    QtyMismatch { client_id: u64, local: u32, engine: u32 },
+    PriceMismatch { client_id: u64, local: u64, engine: u64 },
+    // the end marker count did not match the entries we got , the diff was skipped
+    IncompleteSnapshot { expected: u32, got: u32 },
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReconcileStats {
+    pub queries: u64,
+    pub completed: u64,
+    pub timed_out: u64,
+    pub discrepancies: u64,
+}
+
+/// Reconcile state of one symbol , lives on SymbolOrders.
+#[derive(Debug, Clone, Default)]
+pub struct Reconciler {
+    pub next_request_id: u64,
+    // the query we are waiting on : request id , when it was sent
+    pub in_flight: Option<(u64, u64)>,
+    pub entries: Vec<SnapshotEntry>,
+    pub last_query: u64, // clock nanos
+    // orders the last snapshot had less of than we did : client id -> (engine qty , our original size then)
+    pub short: FxHashMap<u64, (u32, u32)>,
+    pub stats: ReconcileStats,
+}
+
+impl Reconciler {
+    /// a new request id if a query is due , `None` while one is still out (unless it timed out)
+    pub fn start(&mut self, now: u64, gap: Duration) -> Option<u64> {
+        if let Some((request_id, sent_at)) = self.in_flight {
+            // the end marker is treated as lost after a full gap
+            if elapsed(now, sent_at) < gap {
+                return None;
+            }
+            eprintln!(" reconcile request {} timed out", request_id);
+            self.stats.timed_out += 1;
+            self.in_flight = None;
+        } else if elapsed(now, self.last_query) < gap {
+            return None;
+        }
+        self.next_request_id += 1;
+        self.in_flight = Some((self.next_request_id, now));
+        self.entries.clear();
+        self.last_query = now;
+        self.stats.queries += 1;
+        Some(self.next_request_id)
+    }
+
+    /// entries outside a query are stale , dropped
+    pub fn on_entry(&mut self, message: &MessageFromApi) {
+        if self.in_flight.is_some() {
+            self.entries.push(SnapshotEntry::from_message(message));
+        }
+    }
+}
+
+/// Diffs the finished snapshot against `orders` and fixes `orders` , cancels for orders only the engine
+/// has go into `cancel_batch`. Returns what was found , nothing if `end` does not close the query in flight.
+pub fn reconcile(
+    orders: &mut SymbolOrders,
+    end: &MessageFromApi,
+    cancel_batch: &mut Vec<CancelData>,
+    now: u64,
+) -> Vec<Discrepancy> {
+    let mut found = Vec::new();
+    let Some((request_id, sent_at)) = orders.reconciler.in_flight else {
+        return found;
+    };
+    if end.order_id != request_id {
+        return found;
+    }
+    orders.reconciler.in_flight = None;
+    let entries = std::mem::take(&mut orders.reconciler.entries);
+    orders.reconciler.stats.completed += 1;
+
+    if entries.len() != end.shares_qty as usize {
+        found.push(Discrepancy::IncompleteSnapshot { expected: end.shares_qty, got: entries.len() as u32 });
+        orders.reconciler.stats.discrepancies += 1;
+        return found;
+    }
+
+    let symbol = orders.symbol;
+    let mut matched = vec![false; entries.len()];
+    let was_short = std::mem::take(&mut orders.reconciler.short);
+    let mut short = FxHashMap::default();
+    orders.retain(|order| {
+        let position = entries.iter().position(|entry| {
+            entry.client_id == order.client_id || order.exchange_order_id == Some(entry.order_id)
+        });
+        let Some(position) = position else {
+            match order.state {
+                // posted after the query , the snapshot cannot know it yet
+                OrderState::PendingNew if order.created_at >= sent_at => return true,
+                OrderState::PendingNew => {
+                    found.push(Discrepancy::NeverReachedEngine { client_id: order.client_id });
+                }
+                _ => {
+                    found.push(Discrepancy::GoneOnEngine {
+                        client_id: order.client_id,
+                        order_id: order.exchange_order_id.unwrap_or(0),
+                    });
+                }
+            }
+            return false;
+        };
+        matched[position] = true;
+        let entry = entries[position];
+
+        if order.state == OrderState::PendingNew {
+            found.push(Discrepancy::AckMissed { client_id: order.client_id, order_id: entry.order_id });
+            order.exchange_order_id = Some(entry.order_id);
+            order.state = OrderState::Active;
+            order.stuck_since = None;
+            if order.cancel_on_ack {
+                cancel_batch.push(CancelData { symbol, client_id: order.client_id, order_id: Some(entry.order_id) });
+                order.mark_pending_cancel(now);
+            }
+        }
+        if order.state == OrderState::PendingReplace {
+            // amended after the query , the snapshot has the old price
+            if order.created_at >= sent_at {
+                return true;
+            }
+            if order.price.to_u64() == Some(entry.price) {
+                found.push(Discrepancy::ReplaceAckMissed { client_id: order.client_id, order_id: entry.order_id });
+                order.exchange_order_id = Some(entry.order_id);
+                order.replaced_from = None;
+                order.state = OrderState::Active;
+                if order.cancel_on_ack {
+                    cancel_batch.push(CancelData { symbol, client_id: order.client_id, order_id: Some(entry.order_id) });
+                    order.mark_pending_cancel(now);
+                }
+            } else {
+                found.push(Discrepancy::ReplaceLost { client_id: order.client_id });
+                order.revert_replace();
+            }
+            order.stuck_since = None;
+        }
+        // the fills behind the last snapshot had a whole gap to arrive , what is still missing is lost
+        if let Some(&(engine, original_size)) = was_short.get(&order.client_id)
+            && original_size == order.original_size
+            && engine < order.remaining_size {
+            found.push(Discrepancy::QtyMismatch { client_id: order.client_id, local: order.remaining_size, engine });
+            order.remaining_size = engine;
+            if order.state == OrderState::Active {
+                order.state = OrderState::PartiallyFilled;
+            }
+        }
+        // may just be a fill still on its way , looked at again on the next snapshot
+        if entry.qty < order.remaining_size {
+            short.insert(order.client_id, (entry.qty, order.original_size));
+        }
+        let price = order.price.to_u64().unwrap_or(0);
+        if price != entry.price {
+            found.push(Discrepancy::PriceMismatch { client_id: order.client_id, local: price, engine: entry.price });
+        }
+        true
+    });
+
+    orders.reconciler.short = short;
+
+    // a ghost the snapshot does not list is gone , one it does list is cancelled below like any unknown order
+    orders.ghosts.retain_mut(|ghost| {
+        let listed = entries.iter().find(|entry| entry.client_id == ghost.client_id || ghost.order_id == Some(entry.order_id));
+        match listed {
+            Some(entry) => {
+                ghost.order_id = Some(entry.order_id);
+                ghost.cancel_sent_at = Some(now);
+                true
+            }
+            // given up on after the query went out , the snapshot may predate it
+            None => ghost.dropped_at > sent_at,
+        }
+    });
+
+    for (entry, matched) in entries.iter().zip(matched) {
+        if !matched {
+            found.push(Discrepancy::UnknownOnEngine { client_id: entry.client_id, order_id: entry.order_id });
+            cancel_batch.push(CancelData { symbol, client_id: entry.client_id, order_id: Some(entry.order_id) });
+        }
+    }
+    orders.reconciler.stats.discrepancies += found.len() as u64;
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::mmbot::types::PendingOrder;
+
+    const SECOND: u64 = 1_000_000_000;
+    const QUERY_AT: u64 = 10 * SECOND;
+
+    fn pending(client_id: u64, created_at: u64) -> PendingOrder {
+        PendingOrder {
+            client_id,
+            exchange_order_id: None,
+            side: Side::BID,
+            price: Decimal::from(100),
+            original_size: 10,
+            remaining_size: 10,
+            state: OrderState::PendingNew,
+            level: 0,
+            created_at,
+            cancel_on_ack: false,
+            cancel_sent_at: 0,
+            cancel_retries: 0,
+            stuck_since: None,
+            replaced_from: None,
+        }
+    }
+
+    fn entry(order_id: u64, client_id: u64) -> MessageFromApi {
+        MessageFromApi { order_id, client_id, ipo_price: 100, shares_qty: 10, symbol: 1, message_type: 6, ..Default::default() }
+    }
+
+    // sends the query at QUERY_AT , feeds `entries` and closes it with an end marker counting `count`
+    fn snapshot(orders: &mut SymbolOrders, entries: &[MessageFromApi], count: u32) -> (Vec<Discrepancy>, Vec<CancelData>) {
+        let request_id = orders.reconciler.start(QUERY_AT, Duration::from_secs(1)).unwrap();
+        for message in entries {
+            orders.reconciler.on_entry(message);
+        }
+        let end = MessageFromApi { order_id: request_id, shares_qty: count, symbol: 1, message_type: 7, ..Default::default() };
+        let mut cancel_batch = Vec::new();
+        let found = reconcile(orders, &end, &mut cancel_batch, QUERY_AT + SECOND);
+        (found, cancel_batch)
+    }
+
+    #[test]
+    fn listed_order_without_its_ack_is_taken_as_accepted() {
+        let mut orders = SymbolOrders::new(1, 0, 1);
+        orders.insert(pending(7, SECOND));
+
+        let (found, cancels) = snapshot(&mut orders, &[entry(70, 7)], 1);
+        assert_eq!(found, vec![Discrepancy::AckMissed { client_id: 7, order_id: 70 }]);
+        assert!(cancels.is_empty());
+        let order = orders.get(7).unwrap();
+        assert_eq!(order.state, OrderState::Active);
+        assert_eq!(order.exchange_order_id, Some(70));
+        assert_eq!(orders.update_by_exchange_id(70, |order| order.client_id), Some(7));
+    }
+
+    #[test]
+    fn unlisted_post_from_before_the_query_never_reached_the_engine() {
+        let mut orders = SymbolOrders::new(1, 0, 1);
+        orders.insert(pending(7, SECOND));
+
+        let (found, _) = snapshot(&mut orders, &[], 0);
+        assert_eq!(found, vec![Discrepancy::NeverReachedEngine { client_id: 7 }]);
+        assert!(orders.is_empty());
+    }
+
+    #[test]
+    fn post_sent_after_the_query_is_left_out_of_the_diff() {
+        let mut orders = SymbolOrders::new(1, 0, 1);
+        orders.insert(pending(7, QUERY_AT));
+
+        let (found, cancels) = snapshot(&mut orders, &[], 0);
+        assert!(found.is_empty());
+        assert!(cancels.is_empty());
+        assert_eq!(orders.get(7).unwrap().state, OrderState::PendingNew);
+    }
+
+    #[test]
+    fn order_only_the_engine_has_is_cancelled() {
+        let mut orders = SymbolOrders::new(1, 0, 1);
+
+        let (found, cancels) = snapshot(&mut orders, &[entry(80, 8)], 1);
+        assert_eq!(found, vec![Discrepancy::UnknownOnEngine { client_id: 8, order_id: 80 }]);
+        assert_eq!(cancels.len(), 1);
+        assert_eq!((cancels[0].symbol, cancels[0].client_id, cancels[0].order_id), (1, 8, Some(80)));
+        assert!(orders.is_empty());
+    }
+
+    #[test]
+    fn snapshot_missing_entries_is_not_diffed() {
+        let mut orders = SymbolOrders::new(1, 0, 1);
+        orders.insert(pending(7, SECOND));
+
+        let (found, cancels) = snapshot(&mut orders, &[entry(80, 8)], 2);
+        assert_eq!(found, vec![Discrepancy::IncompleteSnapshot { expected: 2, got: 1 }]);
+        assert!(cancels.is_empty());
+        assert_eq!(orders.get(7).unwrap().state, OrderState::PendingNew);
+        assert_eq!(orders.reconciler.stats.completed, 1);
+        assert!(orders.reconciler.in_flight.is_none());
+    }
+}
@@ -27,14 +27,17 @@ impl RollingPrice{
    
     pub fn as_slice_for_volatility(&mut self) -> &[Decimal] {
       
-        let contiguous = self.deque.make_contiguous();
-        contiguous
+        self.deque.make_contiguous()
     }
 
     pub fn len(&self) -> usize {
         self.deque.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.deque.is_empty()
+    }
+
     pub fn clear(&mut self, ipo_price: Decimal) {
         self.deque.clear();
         for _ in 0..self.capacity {
@@ -0,0 +1,58 @@
+use std::io::Write;
+use std::path::Path;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+// what the bot left behind when it stopped , written once on the way out
+// the exit code tells a supervisor whether anything may still be resting on the engine
+
+pub const EXIT_CLEAN: i32 = 0;
+// the wait ran out (or was cut short) with orders still unacked
+pub const EXIT_ORDERS_LEFT: i32 = 3;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolReport {
+    pub symbol: u32,
+    pub mode: String,
+    pub inventory: Decimal,
+    pub avg_entry_price: Decimal,
+    pub realized_pnl: Decimal,
+    pub unrealized_pnl: Decimal,
+    pub total_pnl: Decimal,
+    // pending orders that never got their cancel ack or final fill
+    pub orders_left: usize,
+    // stuck orders we gave up on that the engine never confirmed gone
+    pub ghosts: usize,
+    pub fill_gap_detected: bool,
+    pub rejects: u64,
+    pub quarantined: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShutdownReport {
+    // the book was empty and no ghost was left on every symbol when we stopped waiting
+    pub clean: bool,
+    pub timed_out: bool,
+    pub waited_ms: u64,
+    pub cancels_sent: usize,
+    pub cancels_dropped: usize,
+    pub fills: usize,
+    pub total_pnl: Decimal,
+    pub symbols: Vec<SymbolReport>,
+}
+
+impl ShutdownReport {
+    pub fn exit_code(&self) -> i32 {
+        if self.clean { EXIT_CLEAN } else { EXIT_ORDERS_LEFT }
+    }
+
+    /// one json object , to `path` or stdout when there is none
+    pub fn write(&self, path: Option<&Path>) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        match path {
+            Some(path) => std::fs::write(path, json + "\n"),
+            None => writeln!(std::io::stdout().lock(), "{}", json),
+        }
+    }
+}
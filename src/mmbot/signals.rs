@@ -0,0 +1,33 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// unix signals turned into flags the main loop polls , the handler itself only stores to an atomic
+// (anything else is not async signal safe)
+
+const MAX_SIGNAL: usize = 64;
+
+static RECEIVED: [AtomicBool; MAX_SIGNAL] = [const { AtomicBool::new(false) }; MAX_SIGNAL];
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    if let Some(flag) = RECEIVED.get(signal as usize) {
+        flag.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Routes `signal` (libc::SIGUSR1 etc) to a flag instead of its default action.
+pub fn install(signal: libc::c_int) -> Result<(), String> {
+    if signal <= 0 || signal as usize >= MAX_SIGNAL {
+        return Err(format!("signal {} out of range", signal));
+    }
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+        return Err(format!("failed to install a handler for signal {}", signal));
+    }
+    Ok(())
+}
+
+/// true once per delivery (or per burst of deliveries) of `signal` since the last call
+pub fn take(signal: libc::c_int) -> bool {
+    RECEIVED
+        .get(signal as usize)
+        .is_some_and(|flag| flag.swap(false, Ordering::SeqCst))
+}
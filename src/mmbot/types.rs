@@ -1,6 +1,13 @@
-use std::time::Instant;
+use std::time::Duration;
 
 use rust_decimal::Decimal;
+use rustc_hash::FxHashMap;
+
+use crate::mmbot::config::MAX_LADDER_LEVELS;
+use crate::mmbot::constants::{REJECT_BACKOFF_AFTER, REJECT_BACKOFF_BASE, REJECT_BACKOFF_MAX};
+use crate::mmbot::client_id::ClientId;
+use crate::mmbot::kill_switch::KillReason;
+use crate::mmbot::reconcile::Reconciler;
 
 // level are basically price levels  how deep to quote 
 
@@ -16,32 +23,230 @@ pub enum QuotingMode {
 }
 
 
+// our orders on one symbol , indexed so acks , fills and the requote dont have to scan them
+// orders sit in a dense vec (removal swaps the last one in) with hash indexes by client and exchange id ,
+// a (side , level) slot array for the ladder and per side counts of every state
+// anything that changes an order goes through here so the indexes never go stale ,
+// side , level and client id of an order never change once it is inserted
 #[derive(Debug)]
 pub struct SymbolOrders {
     pub symbol: u32,
-    pub pending_orders: Vec<PendingOrder>,
-    pub next_client_id: u64,
-    pub last_quote_time: Instant,
+    orders: Vec<PendingOrder>,
+    by_client: FxHashMap<u64, usize>,
+    by_exchange: FxHashMap<u64, usize>,
+    // side * MAX_LADDER_LEVELS + level -> positions in `orders`
+    slots: Vec<Vec<usize>>,
+    // [side][state] -> how many orders
+    counts: [[usize; OrderState::COUNT]; 2],
+    // client ids are ClientId { epoch , symbol , seq }
+    pub epoch: u16,
+    pub next_seq: u32,
+    pub last_quote_time: u64, // clock nanos
+    pub rejects: RejectTracker,
+    pub ack_timeouts: AckTimeoutStats,
+    pub reconciler: Reconciler,
+    // stuck orders taken off the book , they may still rest on the engine
+    pub ghosts: Vec<Ghost>,
+}
+
+// an order that got stuck and left the book after ghost_expiry
+// it stays here until a cancel ack , a reject or a later snapshot shows it is not on the engine
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ghost{
+    pub client_id : u64 ,
+    pub order_id : Option<u64> ,
+    pub dropped_at : u64 , // clock nanos
+    pub cancel_sent_at : Option<u64> , // clock nanos , last cancel we sent for it , resent after AckTimeouts::cancel
+}
+
+// what the indexes depend on , compared before and after a change
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct IndexKey{
+    state : OrderState ,
+    exchange_order_id : Option<u64> ,
+}
+
+impl IndexKey{
+    fn of(order : &PendingOrder)->Self{
+        Self { state : order.state , exchange_order_id : order.exchange_order_id }
+    }
+}
+
+fn slot_of(side : Side , level : usize)->Option<usize>{
+    (level < MAX_LADDER_LEVELS).then_some(side as usize * MAX_LADDER_LEVELS + level)
 }
 
 
 impl SymbolOrders{
-    pub fn new(symbol : u32)->Self{
+    pub fn new(symbol : u32 , now : u64 , epoch : u16)->Self{
         Self { 
             symbol, 
-            pending_orders: Vec::new(), 
-            next_client_id: 1, 
-            last_quote_time: Instant::now() 
+            orders: Vec::new(), 
+            by_client: FxHashMap::default(),
+            by_exchange: FxHashMap::default(),
+            slots: vec![Vec::new(); 2 * MAX_LADDER_LEVELS],
+            counts: [[0; OrderState::COUNT]; 2],
+            epoch,
+            next_seq: 1, 
+            last_quote_time: now ,
+            rejects: RejectTracker::default(),
+            ack_timeouts: AckTimeoutStats::default(),
+            reconciler: Reconciler::default(),
+            ghosts: Vec::new()
         }
     }
 
+    // symbol must fit in 16 bits , checked when the symbol is added
     pub fn alloc_client_id(&mut self) -> u64 {
-        let id = self.next_client_id;
-        self.next_client_id += 1;
-        id
+        let seq = self.next_seq;
+        // seq 0 is skipped so no id is ever 0
+        self.next_seq = self.next_seq.checked_add(1).unwrap_or(1);
+        ClientId { epoch : self.epoch , symbol : self.symbol as u16 , seq }.encode()
+    }
+
+    pub fn len(&self)->usize{
+        self.orders.len()
+    }
+
+    pub fn is_empty(&self)->bool{
+        self.orders.is_empty()
+    }
+
+    pub fn iter(&self)->std::slice::Iter<'_ , PendingOrder>{
+        self.orders.iter()
+    }
+
+    /// orders of one side in `state`
+    pub fn count(&self , side : Side , state : OrderState)->usize{
+        self.counts[side as usize][state as usize]
+    }
+
+    /// orders at one ladder level , whatever their state
+    /// config caps ladders at MAX_LADDER_LEVELS , nothing is slotted past it
+    pub fn at_level(&self , side : Side , level : usize)->impl Iterator<Item = &PendingOrder>{
+        let positions : &[usize] = match slot_of(side, level) {
+            Some(slot) => &self.slots[slot],
+            None => &[],
+        };
+        positions.iter().map(|&position| &self.orders[position])
+    }
+
+    pub fn insert(&mut self , order : PendingOrder){
+        let position = self.orders.len();
+        self.by_client.insert(order.client_id, position);
+        if let Some(order_id) = order.exchange_order_id {
+            self.by_exchange.insert(order_id, position);
+        }
+        if let Some(slot) = slot_of(order.side, order.level) {
+            self.slots[slot].push(position);
+        }
+        self.counts[order.side as usize][order.state as usize] += 1;
+        self.orders.push(order);
+    }
+
+    pub fn get(&self , client_id : u64)->Option<&PendingOrder>{
+        self.by_client.get(&client_id).map(|&position| &self.orders[position])
+    }
+
+    /// runs `f` on the order with `client_id` , `None` if we dont have it
+    pub fn update<R , F : FnOnce(&mut PendingOrder)->R>(&mut self , client_id : u64 , f : F)->Option<R>{
+        let position = *self.by_client.get(&client_id)?;
+        Some(self.update_at(position, f))
+    }
+
+    pub fn update_by_exchange_id<R , F : FnOnce(&mut PendingOrder)->R>(&mut self , order_id : u64 , f : F)->Option<R>{
+        let position = *self.by_exchange.get(&order_id)?;
+        Some(self.update_at(position, f))
+    }
+
+    pub fn remove(&mut self , client_id : u64)->Option<PendingOrder>{
+        let position = *self.by_client.get(&client_id)?;
+        Some(self.remove_at(position))
+    }
+
+    pub fn remove_by_exchange_id(&mut self , order_id : u64)->Option<PendingOrder>{
+        let position = *self.by_exchange.get(&order_id)?;
+        Some(self.remove_at(position))
     }
-}
 
+    /// runs `f` on every order , indexes are fixed up after each call
+    pub fn for_each_mut<F : FnMut(&mut PendingOrder)>(&mut self , mut f : F){
+        for position in 0..self.orders.len() {
+            let before = IndexKey::of(&self.orders[position]);
+            f(&mut self.orders[position]);
+            self.reindex(position, before);
+        }
+    }
+
+    /// keeps the orders `f` returns true for , `f` may change them
+    pub fn retain<F : FnMut(&mut PendingOrder)->bool>(&mut self , mut f : F){
+        let mut position = 0;
+        while position < self.orders.len() {
+            let before = IndexKey::of(&self.orders[position]);
+            let keep = f(&mut self.orders[position]);
+            self.reindex(position, before);
+            if keep {
+                position += 1;
+            } else {
+                // the last order is swapped in here , look at this position again
+                self.remove_at(position);
+            }
+        }
+    }
+
+    fn update_at<R , F : FnOnce(&mut PendingOrder)->R>(&mut self , position : usize , f : F)->R{
+        let before = IndexKey::of(&self.orders[position]);
+        let result = f(&mut self.orders[position]);
+        self.reindex(position, before);
+        result
+    }
+
+    fn remove_at(&mut self , position : usize)->PendingOrder{
+        let order = self.orders.swap_remove(position);
+        self.by_client.remove(&order.client_id);
+        if let Some(order_id) = order.exchange_order_id {
+            self.by_exchange.remove(&order_id);
+        }
+        if let Some(slot) = slot_of(order.side, order.level) {
+            self.slots[slot].retain(|&at| at != position);
+        }
+        self.counts[order.side as usize][order.state as usize] -= 1;
+
+        // the order that was last now lives at `position`
+        let moved_from = self.orders.len();
+        if position < moved_from {
+            let moved = &self.orders[position];
+            self.by_client.insert(moved.client_id, position);
+            if let Some(order_id) = moved.exchange_order_id {
+                self.by_exchange.insert(order_id, position);
+            }
+            if let Some(slot) = slot_of(moved.side, moved.level)
+                && let Some(at) = self.slots[slot].iter_mut().find(|at| **at == moved_from) {
+                *at = position;
+            }
+        }
+        order
+    }
+
+    fn reindex(&mut self , position : usize , before : IndexKey){
+        let order = &self.orders[position];
+        let after = IndexKey::of(order);
+        if after == before {
+            return;
+        }
+        let side = order.side as usize;
+        self.counts[side][before.state as usize] -= 1;
+        self.counts[side][after.state as usize] += 1;
+        if before.exchange_order_id != after.exchange_order_id {
+            if let Some(order_id) = before.exchange_order_id {
+                self.by_exchange.remove(&order_id);
+            }
+            if let Some(order_id) = after.exchange_order_id {
+                self.by_exchange.insert(order_id, position);
+            }
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq , Copy)]
 pub enum Side {
@@ -54,10 +259,15 @@ pub enum OrderState {
     PendingNew, // send but ack not reicved 
     Active,
     PendingCancel,
+    PendingReplace, // amend sent , price and qty already hold the new values
     PartiallyFilled,
     CompletelyFilled
 }
 
+impl OrderState{
+    pub const COUNT : usize = 6;
+}
+
 
 #[derive(Debug, Clone , Copy)]
 pub struct PendingOrder{
@@ -69,7 +279,65 @@ pub struct PendingOrder{
     pub remaining_size: u32,
     pub state: OrderState,
     pub level: usize,  // Which level in the ladder (0-9)
-    pub created_at : Instant ,
+    pub created_at : u64 , // clock nanos
+    pub cancel_on_ack : bool , // cancel requested while PendingNew , sent once the accept ack arrives
+    pub cancel_sent_at : u64 , // clock nanos , last cancel we sent for it
+    pub cancel_retries : u32 , 
+    pub stuck_since : Option<u64> , // gave up waiting for its ack , dropped as a ghost after AckTimeouts::ghost_expiry
+    pub replaced_from : Option<(Decimal , u32 , u32)> , // price , original and remaining size before the amend in flight
+}
+
+impl PendingOrder{
+    // every cancel goes through here so the ack timeout knows when it was sent
+    pub fn mark_pending_cancel(&mut self , now : u64){
+        self.state = OrderState::PendingCancel;
+        self.cancel_sent_at = now;
+        self.cancel_retries = 0;
+        self.stuck_since = None;
+    }
+
+    // the new values are taken right away so the ladder sees the level as filled , a reject puts the old ones back
+    pub fn mark_pending_replace(&mut self , price : Decimal , qty : u32 , now : u64){
+        self.replaced_from = Some((self.price , self.original_size , self.remaining_size));
+        self.state = OrderState::PendingReplace;
+        self.price = price;
+        self.original_size = qty;
+        self.remaining_size = qty;
+        // the ack timeout and the max age both run from the amend
+        self.created_at = now;
+    }
+
+    // a fill comes off what rests now and , while an amend is in flight , off the old values a reject puts back
+    // returns true once nothing is left and no amend is outstanding , the order can go
+    pub fn apply_fill(&mut self , qty : u32)->bool{
+        self.remaining_size = self.remaining_size.saturating_sub(qty);
+        if let Some((_ , _ , remaining_size)) = &mut self.replaced_from {
+            *remaining_size = remaining_size.saturating_sub(qty);
+        }
+        match self.state {
+            // the amend's ack or reject settles it
+            OrderState::PendingReplace => false ,
+            _ if self.remaining_size == 0 => {
+                self.state = OrderState::CompletelyFilled;
+                true
+            }
+            OrderState::Active => {
+                self.state = OrderState::PartiallyFilled;
+                false
+            }
+            // a cancel in flight keeps its state , its ack settles the order
+            _ => false ,
+        }
+    }
+
+    pub fn revert_replace(&mut self){
+        if let Some((price , original_size , remaining_size)) = self.replaced_from.take() {
+            self.price = price;
+            self.original_size = original_size;
+            self.remaining_size = remaining_size;
+        }
+        self.state = if self.remaining_size < self.original_size { OrderState::PartiallyFilled } else { OrderState::Active };
+    }
 }
 
 
@@ -78,7 +346,49 @@ pub struct PendingOrder{
 pub enum MmError{
     SymbolNotFound ,
     ClienIdNotFound , 
-    CouldNotCalculateQuotes
+    CouldNotCalculateQuotes ,
+    // records were skipped , the record that exposed it was still applied
+    SequenceGap { expected : u64 , got : u64 },
+    // duplicate or reordered record , older than what we already applied , dropped
+    StaleSequence { expected : u64 , got : u64 },
+    // parameter update that is unknown or would leave the symbol config invalid
+    InvalidParam(String)
+}
+
+
+// tracks the per symbol sequence numbers of one engine stream (feed or fills)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SequenceTracker{
+    pub last_seq : u64 ,  // 0 until the first record is seen
+    pub gaps : u64 ,      // how many times records went missing
+    pub missed : u64 ,    // total records missing across all gaps
+    pub stale : u64 ,     // duplicates and reordered records dropped
+}
+
+impl SequenceTracker{
+    pub fn observe(&mut self , seq : u64)->Result<() , MmError>{
+        // first record after startup , whatever the engine is at becomes our base
+        if self.last_seq == 0 {
+            self.last_seq = seq;
+            return Ok(());
+        }
+
+        let expected = self.last_seq + 1;
+        if seq == expected {
+            self.last_seq = seq;
+            return Ok(());
+        }
+
+        if seq < expected {
+            self.stale += 1;
+            return Err(MmError::StaleSequence { expected, got: seq });
+        }
+
+        self.gaps += 1;
+        self.missed += seq - expected;
+        self.last_seq = seq;
+        Err(MmError::SequenceGap { expected, got: seq })
+    }
 }
 
 
@@ -92,6 +402,96 @@ pub enum ApiMessageType{
     AddSymbolMessage = 0 , 
     OrderAcceptedAck = 1 ,
     OrderCancelledAck = 2 ,
+    UpdateParam = 3 ,
+    KillSwitch = 4 ,
+    OrderRejected = 5 ,
+    OpenOrderEntry = 6 ,
+    OpenOrdersEnd = 7 ,
+    OrderReplacedAck = 8 ,
+}
+
+// why the engine turned an order down , carried in MessageFromApi::reject_reason
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason{
+    Other = 0 ,
+    PriceOutOfBand = 1 ,
+    InsufficientPosition = 2 ,
+    RateLimited = 3 ,
+    UnknownSymbol = 4 ,
+    InvalidQuantity = 5 ,
+    UnknownOrder = 6 , // cancel for an order the engine no longer has
+}
+
+impl RejectReason{
+    pub const COUNT : usize = 7;
+
+    // codes we dont know yet are counted as Other
+    pub fn from_u8(code : u8)->Self{
+        match code {
+            1 => RejectReason::PriceOutOfBand ,
+            2 => RejectReason::InsufficientPosition ,
+            3 => RejectReason::RateLimited ,
+            4 => RejectReason::UnknownSymbol ,
+            5 => RejectReason::InvalidQuantity ,
+            6 => RejectReason::UnknownOrder ,
+            _ => RejectReason::Other
+        }
+    }
+}
+
+// how often acks went missing on a symbol
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AckTimeoutStats{
+    pub new_ack_timeouts    : u64 , // posts never accepted nor rejected
+    pub cancel_ack_timeouts : u64 , // every time a cancel went unanswered , retries included
+    pub cancel_retries      : u64 , 
+    pub quarantines         : u64 , 
+    pub ghosts_dropped      : u64 , // stuck orders taken off the book
+}
+
+// per symbol reject counters and the quoting backoff they drive
+#[derive(Debug, Clone, Default)]
+pub struct RejectTracker{
+    pub counts       : [u64 ; RejectReason::COUNT] , // indexed by reason code
+    pub last_reason  : Option<RejectReason> ,
+    pub consecutive  : u32 , // same reason in a row , reset by an accept ack
+    pub backoffs     : u32 , // backoffs started since the last accept , each one twice as long
+    pub backoff_until: u64 , // clock nanos , no new quotes before this
+}
+
+impl RejectTracker{
+    /// counts the reject , returns the backoff it started (if it did)
+    /// rejects that come in while a backoff runs (the rest of the same ladder) do not extend it
+    pub fn record(&mut self , reason : RejectReason , now : u64)->Option<Duration>{
+        self.counts[reason as usize] += 1;
+        if self.last_reason == Some(reason) {
+            self.consecutive += 1;
+        } else {
+            self.last_reason = Some(reason);
+            self.consecutive = 1;
+        }
+        if self.consecutive < REJECT_BACKOFF_AFTER || self.in_backoff(now) {
+            return None;
+        }
+        let backoff = REJECT_BACKOFF_BASE.saturating_mul(1 << self.backoffs.min(16)).min(REJECT_BACKOFF_MAX);
+        self.backoffs += 1;
+        self.backoff_until = now.saturating_add(backoff.as_nanos() as u64);
+        Some(backoff)
+    }
+
+    pub fn accepted(&mut self){
+        self.last_reason = None;
+        self.consecutive = 0;
+        self.backoffs = 0;
+    }
+
+    pub fn in_backoff(&self , now : u64)->bool{
+        now < self.backoff_until
+    }
+
+    pub fn total(&self)->u64{
+        self.counts.iter().sum()
+    }
 }
 
 
@@ -111,6 +511,17 @@ pub struct CancelData{
 }
 
 
+// moves a resting order to a new price and qty , keeps its client id
+#[derive(Debug , Clone, Copy)]
+pub struct ReplaceData{
+    pub symbol : u32 , 
+    pub client_id : u64 , 
+    pub order_id  : u64 , 
+    pub price : Decimal , 
+    pub qty   : u32 , 
+    pub side  : Side , 
+}
+
 #[derive(Debug , Clone, Copy)]
 pub struct PostData{
     pub symbol : u32 ,
@@ -129,7 +540,165 @@ pub struct TargetQuotes{
     pub qty : u32
 }
 
+// (exchange order id , client id) to cancel and the new quotes to post
+// what incremental_requote wants changed on the book
+pub struct RequoteOrders{
+    pub cancels : Vec<(u64 , u64)> , // (order_id , client_id)
+    pub posts   : Vec<PostData> , 
+    pub amends  : Vec<ReplaceData> , 
+}
+
 pub struct TargetLadder {
     pub bids : Vec<TargetQuotes> , 
     pub asks : Vec<TargetQuotes>,
-}
\ No newline at end of file
+}
+#[derive(Debug , Clone, Copy, PartialEq)]
+pub struct ModeChange{
+    pub symbol : u32 , 
+    pub from   : QuotingMode , 
+    pub to     : QuotingMode 
+}
+
+// what one MarketMaker::tick did , inputs drained and orders sent
+#[derive(Debug , Clone, Default, PartialEq)]
+pub struct TickSummary{
+    pub fills          : usize , 
+    pub feeds          : usize , // raw feed records consumed , before conflation
+    pub api_messages   : usize , 
+    pub posted         : usize , 
+    pub posts_dropped  : usize , // order queue was full or the price did not fit the wire
+    pub cancels_sent   : usize , 
+    pub cancels_dropped: usize , 
+    pub amends_sent    : usize , 
+    pub amends_dropped : usize , // same as posts_dropped
+    pub mode_changes   : Vec<ModeChange> , 
+    pub kill_engaged   : Option<KillReason> , // the kill switch cancelled everything this tick
+    pub kill_completed : bool , // the last cancel ack after a kill came in this tick
+    pub rejects        : usize , 
+    pub ack_timeouts   : usize , 
+    pub discrepancies  : usize , // found by reconciles that finished this tick
+    pub unknown_acks   : usize , // accept , cancel and replace acks for a symbol we dont have
+    pub symbols_rejected : usize , // add symbol messages for ids above MAX_CLIENT_ID_SYMBOL
+}
+
+impl TickSummary{
+    /// nothing came in and nothing went out
+    pub fn is_idle(&self)->bool{
+        self.fills + self.feeds + self.api_messages == 0
+            && self.posted + self.posts_dropped == 0
+            && self.cancels_sent + self.cancels_dropped == 0
+            && self.amends_sent + self.amends_dropped == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(client_id : u64 , side : Side , level : usize)->PendingOrder{
+        PendingOrder {
+            client_id ,
+            exchange_order_id : None ,
+            side ,
+            price : Decimal::from(100 + level as u64) ,
+            original_size : 10 ,
+            remaining_size : 10 ,
+            state : OrderState::PendingNew ,
+            level ,
+            created_at : 0 ,
+            cancel_on_ack : false ,
+            cancel_sent_at : 0 ,
+            cancel_retries : 0 ,
+            stuck_since : None ,
+            replaced_from : None ,
+        }
+    }
+
+    // every index has to agree with a plain scan of the orders
+    fn assert_indexed(orders : &SymbolOrders){
+        const STATES : [OrderState ; OrderState::COUNT] = [OrderState::PendingNew , OrderState::Active , OrderState::PendingCancel , OrderState::PendingReplace , OrderState::PartiallyFilled , OrderState::CompletelyFilled];
+        for side in [Side::BID , Side::ASK] {
+            for state in STATES {
+                let scanned = orders.iter().filter(|order| order.side == side && order.state == state).count();
+                assert_eq!(orders.count(side, state), scanned, "{:?} {:?}" , side , state);
+            }
+            for level in 0..MAX_LADDER_LEVELS {
+                let mut indexed : Vec<u64> = orders.at_level(side, level).map(|order| order.client_id).collect();
+                let mut scanned : Vec<u64> = orders.iter().filter(|order| order.side == side && order.level == level).map(|order| order.client_id).collect();
+                indexed.sort_unstable();
+                scanned.sort_unstable();
+                assert_eq!(indexed, scanned, "{:?} level {}" , side , level);
+            }
+        }
+        let mut with_exchange_id = 0;
+        for order in orders.iter() {
+            assert_eq!(orders.get(order.client_id).map(|found| found.client_id), Some(order.client_id));
+            if let Some(order_id) = order.exchange_order_id {
+                with_exchange_id += 1;
+                assert_eq!(orders.by_exchange.get(&order_id).map(|&position| orders.orders[position].client_id), Some(order.client_id));
+            }
+        }
+        assert_eq!(orders.by_client.len(), orders.len());
+        assert_eq!(orders.by_exchange.len(), with_exchange_id);
+    }
+
+    #[test]
+    fn symbol_orders_indexes_follow_every_change(){
+        let mut orders = SymbolOrders::new(1, 0, 1);
+        for client_id in 1..=8 {
+            let side = if client_id % 2 == 0 { Side::BID } else { Side::ASK };
+            orders.insert(pending(client_id, side, (client_id as usize / 2) % 3));
+            assert_indexed(&orders);
+        }
+
+        // acks hand out exchange ids
+        for client_id in 1..=6 {
+            orders.update(client_id, |order| {
+                order.exchange_order_id = Some(client_id + 100);
+                order.state = OrderState::Active;
+            }).unwrap();
+            assert_indexed(&orders);
+        }
+        orders.update_by_exchange_id(103, |order| order.mark_pending_cancel(1)).unwrap();
+        orders.update_by_exchange_id(104, |order| order.apply_fill(4)).unwrap();
+        assert_eq!(orders.update_by_exchange_id(999, |_| ()), None);
+        assert_indexed(&orders);
+
+        // removing from the middle swaps the last order into the hole
+        assert_eq!(orders.remove(2).map(|order| order.client_id), Some(2));
+        assert_indexed(&orders);
+        assert_eq!(orders.remove_by_exchange_id(101).map(|order| order.client_id), Some(1));
+        assert_indexed(&orders);
+        assert!(orders.remove(2).is_none());
+        assert!(orders.update_by_exchange_id(101, |_| ()).is_none());
+
+        // a late ack on a swapped order still finds it
+        orders.update(8, |order| order.exchange_order_id = Some(108)).unwrap();
+        assert_indexed(&orders);
+        assert_eq!(orders.update_by_exchange_id(108, |order| order.client_id), Some(8));
+
+        orders.retain(|order| {
+            if order.state == OrderState::Active {
+                order.mark_pending_cancel(2);
+            }
+            order.client_id % 3 != 0
+        });
+        assert_indexed(&orders);
+        assert_eq!(orders.len(), 4);
+        assert!(orders.get(3).is_none() && orders.get(6).is_none());
+        assert_eq!(orders.count(Side::ASK, OrderState::PendingCancel), 1);
+        assert_eq!(orders.count(Side::BID, OrderState::PendingNew), 1);
+        assert_eq!(orders.count(Side::BID, OrderState::PartiallyFilled), 1);
+
+        orders.retain(|_| false);
+        assert_indexed(&orders);
+        assert!(orders.is_empty());
+    }
+
+    #[test]
+    fn tick_that_only_amends_is_not_idle(){
+        assert!(TickSummary::default().is_idle());
+        assert!(!TickSummary { amends_sent : 1 , ..Default::default() }.is_idle());
+        assert!(!TickSummary { amends_dropped : 1 , ..Default::default() }.is_idle());
+    }
+}
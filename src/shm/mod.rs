@@ -1,3 +1,5 @@
+pub mod queue;
+pub mod wait;
 pub mod fill_queue_mm;
 pub mod feed_queue_mm;
 pub mod order_queue_mm;
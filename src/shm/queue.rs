@@ -0,0 +1,999 @@
+use memmap2::{Mmap, MmapMut};
+use std::fs::{self, File, OpenOptions };
+use std::marker::PhantomData;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::shm::wait::{Backoff, WaitStrategy};
+
+// generic SPSC ring over a shared memory file , one instance per record type
+// every queue module only declares its record and implements ShmRecord for it
+
+/// A plain `#[repr(C)]` record that can live in a shared memory ring.
+pub trait ShmRecord: Copy {
+    /// Magic stored in the header , identifies which record type the file holds
+    /// must be unique per record type so opening the wrong file fails in `open()`
+    const MAGIC: u32;
+    /// Layout version of the record , bump whenever the struct changes
+    const VERSION: u32;
+    /// Expected `size_of::<Self>()` , must match the peer (Go engine)
+    const SIZE: usize;
+}
+
+// QueueHeader with cache-line padding matching Go
+#[repr(C)]
+pub struct QueueHeader {
+    producer_head: AtomicU64, // offset 0
+    _pad1: [u8; 56],          // pad to 64B
+    consumer_tail: AtomicU64, // offset 64
+    _pad2: [u8; 56],          // pad to 128B
+    magic: AtomicU32,         // offset 128
+    capacity: AtomicU32,      // offset 132
+    version: AtomicU32,       // offset 136 , ShmRecord::VERSION of the writer
+    record_size: AtomicU32,   // offset 140 , size_of the record the writer was built with
+    _pad3: [u8; 48],          // pad to 192B
+
+    // telemetry , each side only ever writes its own cache line
+    // total enqueued / dequeued are producer_head / consumer_tail themselves
+    high_watermark: AtomicU64,        // offset 192 , peak depth seen by the producer
+    full_rejections: AtomicU64,       // offset 200 , records refused with QueueFull
+    producer_heartbeat_ns: AtomicU64, // offset 208 , unix nanos of the producer's last heartbeat
+    _pad4: [u8; 40],                  // pad to 256B
+    consumer_heartbeat_ns: AtomicU64, // offset 256 , unix nanos of the consumer's last heartbeat
+    _pad5: [u8; 56],                  // pad to 320B
+}
+
+// capacity used when the creator has no reason to pick another one
+pub const DEFAULT_QUEUE_CAPACITY: usize = 65536;
+pub const HEADER_SIZE: usize = std::mem::size_of::<QueueHeader>();
+
+// Compile-time layout assertions (fail build if wrong)
+const _: () = assert!(HEADER_SIZE == 320, "QueueHeader must be 320 bytes");
+const _: () = {
+    // Verify ConsumerTail is at offset 64
+    assert!(
+        std::mem::offset_of!(QueueHeader, consumer_tail) == 64,
+        "ConsumerTail must be at offset 64"
+    );
+    assert!(
+        std::mem::offset_of!(QueueHeader, version) == 136,
+        "Version must be at offset 136"
+    );
+    assert!(
+        std::mem::offset_of!(QueueHeader, high_watermark) == 192,
+        "HighWatermark must be at offset 192"
+    );
+    assert!(
+        std::mem::offset_of!(QueueHeader, consumer_heartbeat_ns) == 256,
+        "ConsumerHeartbeat must be at offset 256"
+    );
+};
+
+/// Point in time copy of a queue's counters , read with relaxed loads so it never
+/// slows down either side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueStats {
+    pub depth: u64,
+    pub capacity: u64,
+    pub total_enqueued: u64,
+    pub total_dequeued: u64,
+    pub high_watermark: u64,
+    pub full_rejections: u64,
+    pub producer_heartbeat_ns: u64, // 0 = producer never sent one
+    pub consumer_heartbeat_ns: u64, // 0 = consumer never sent one
+}
+
+impl QueueStats {
+    /// time since the producer's last heartbeat , None if it never sent one
+    pub fn producer_silence(&self) -> Option<Duration> {
+        heartbeat_age(self.producer_heartbeat_ns)
+    }
+
+    /// time since the consumer's last heartbeat , None if it never sent one
+    pub fn consumer_silence(&self) -> Option<Duration> {
+        heartbeat_age(self.consumer_heartbeat_ns)
+    }
+}
+
+// ring indexes are masked , so the capacity has to be a power of two
+fn check_capacity(capacity: u64) -> Result<u64, QueueError> {
+    if capacity == 0 || !capacity.is_power_of_two() || capacity > 1 << 31 {
+        return Err(QueueError::InvalidCapacity { got: capacity });
+    }
+    Ok(capacity)
+}
+
+fn unix_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+fn heartbeat_age(heartbeat_ns: u64) -> Option<Duration> {
+    if heartbeat_ns == 0 {
+        return None;
+    }
+    Some(Duration::from_nanos(unix_nanos().saturating_sub(heartbeat_ns)))
+}
+
+#[derive(Debug)]
+pub struct ShmQueue<T: ShmRecord> {
+    mmap: MmapMut,
+    header_ptr: *mut QueueHeader, // Cached pointer
+    records_ptr: *mut T,          // Cached records pointer
+    wait: WaitStrategy,           // used by the blocking / timed dequeues
+    capacity: u64,                // records in the ring , power of two
+    mask: u64,                    // capacity - 1 , slot = index & mask
+    _marker: PhantomData<T>,
+}
+
+impl<T: ShmRecord> ShmQueue<T> {
+    // evaluated once per record type , fails the build if the record drifted from its declared size
+    const RECORD_SIZE: usize = {
+        assert!(std::mem::size_of::<T>() == T::SIZE, "record size does not match ShmRecord::SIZE");
+        T::SIZE
+    };
+
+    fn total_size(capacity: u64) -> u64 {
+        HEADER_SIZE as u64 + capacity * Self::RECORD_SIZE as u64
+    }
+
+    /// `capacity` is the number of records , a power of two that fits the u32 header field
+    pub fn create<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self, QueueError> {
+        let capacity = check_capacity(capacity as u64)?;
+        let _ = fs::remove_file(&path);
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true) // O_EXCL
+            .mode(0o666)
+            .open(&path)
+            .map_err(|e| QueueError::FileOpen(e.to_string()))?;
+
+        Self::init_file(&file, capacity)
+    }
+
+    /// Opens an existing queue , the capacity is whatever its creator chose (read from the header).
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, QueueError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| QueueError::FileOpen(e.to_string()))?;
+
+        let file_len = Self::file_len(&file)?;
+        // only the header has to be there up front , the header fields tell us
+        // *why* the file is wrong before we compare the full size
+        if file_len < HEADER_SIZE as u64 {
+            return Err(QueueError::InvalidSize {
+                got: file_len,
+                expected: HEADER_SIZE as u64,
+            });
+        }
+
+        let queue = Self::map_file(&file, 0)?;
+        let capacity = queue.validate(file_len, None)?;
+        Ok(queue.with_capacity(capacity))
+    }
+
+    /// Crash-safe variant of `create` for restarts.
+    /// An existing valid file with the same `capacity` is reused as is , `producer_head`/`consumer_tail`
+    /// and any records still in flight survive. A missing or empty file is initialized fresh.
+    /// A corrupt file (too short , or a header no ring could have) is reinitialized in place (same inode ,
+    /// so a peer that still has it mapped sees the reset instead of a deleted file) and
+    /// `Some(QueueError::Reinitialized)` is returned next to the queue so the caller can report it.
+    /// A healthy queue of another record type , layout version or capacity is someone else's file ,
+    /// its magic / version / record size / capacity error is returned and the file is left alone.
+    pub fn open_or_create<P: AsRef<Path>>(path: P, capacity: usize) -> Result<(Self, Option<QueueError>), QueueError> {
+        let capacity = check_capacity(capacity as u64)?;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o666)
+            .open(&path)
+            .map_err(|e| QueueError::FileOpen(e.to_string()))?;
+
+        let file_len = Self::file_len(&file)?;
+        if file_len == 0 {
+            // truly fresh file , nothing to lose
+            return Ok((Self::init_file(&file, capacity)?, None));
+        }
+
+        let cause = if file_len < HEADER_SIZE as u64 {
+            QueueError::InvalidSize {
+                got: file_len,
+                expected: Self::total_size(capacity),
+            }
+        } else {
+            let queue = Self::map_file(&file, 0)?;
+            match queue.validate(file_len, Some(capacity)) {
+                Ok(capacity) => return Ok((queue.with_capacity(capacity), None)),
+                Err(e) if e.is_corruption() => e,
+                Err(e) => return Err(e),
+            }
+            // the old mapping is dropped here , before the file gets resized
+        };
+
+        let queue = Self::init_file(&file, capacity)?;
+        Ok((queue, Some(QueueError::Reinitialized { cause: Box::new(cause) })))
+    }
+
+    fn file_len(file: &File) -> Result<u64, QueueError> {
+        file.metadata()
+            .map(|metadata| metadata.len())
+            .map_err(|e| QueueError::FileStat(e.to_string()))
+    }
+
+    /// size the file , map it and write a fresh header (empty queue)
+    fn init_file(file: &File, capacity: u64) -> Result<Self, QueueError> {
+        file.set_len(Self::total_size(capacity))
+            .map_err(|e| QueueError::FileStat(e.to_string()))?;
+
+        file.sync_all()
+            .map_err(|e| QueueError::FileStat(e.to_string()))?;
+
+        let queue = Self::map_file(file, capacity)?;
+        let header = queue.header();
+
+        header.producer_head.store(0, Ordering::SeqCst);
+        header.consumer_tail.store(0, Ordering::SeqCst);
+        header.magic.store(T::MAGIC, Ordering::SeqCst);
+        header.capacity.store(capacity as u32, Ordering::SeqCst);
+        header.version.store(T::VERSION, Ordering::SeqCst);
+        header.record_size.store(Self::RECORD_SIZE as u32, Ordering::SeqCst);
+        header.high_watermark.store(0, Ordering::SeqCst);
+        header.full_rejections.store(0, Ordering::SeqCst);
+        header.producer_heartbeat_ns.store(0, Ordering::SeqCst);
+        header.consumer_heartbeat_ns.store(0, Ordering::SeqCst);
+
+        queue.flush()?;
+        Ok(queue)
+    }
+
+    fn map_file(file: &File, capacity: u64) -> Result<Self, QueueError> {
+        let mut mmap =
+            unsafe { MmapMut::map_mut(file) }.map_err(|e| QueueError::Mmap(e.to_string()))?;
+
+        if let Err(e) = mmap.lock() {
+            eprintln!("Warning: failed to mlock: {}", e);
+        }
+
+        // Cache both pointers
+        let header_ptr = mmap.as_mut_ptr() as *mut QueueHeader;
+        let records_ptr = unsafe { mmap.as_mut_ptr().add(HEADER_SIZE) as *mut T };
+
+        Ok(ShmQueue {
+            mmap,
+            header_ptr,
+            records_ptr,
+            wait: WaitStrategy::default(),
+            capacity,
+            mask: capacity.wrapping_sub(1),
+            _marker: PhantomData,
+        })
+    }
+
+    fn with_capacity(mut self, capacity: u64) -> Self {
+        self.capacity = capacity;
+        self.mask = capacity - 1;
+        self
+    }
+
+    /// checks the header against this record type , returns the capacity it declares
+    /// `expected_capacity` is Some when the caller insists on a particular capacity
+    fn validate(&self, file_len: u64, expected_capacity: Option<u64>) -> Result<u64, QueueError> {
+        let header = self.header();
+        let magic = header.magic.load(Ordering::Relaxed);
+        if magic != T::MAGIC {
+            return Err(QueueError::InvalidMagic { got: magic, expected: T::MAGIC });
+        }
+
+        let version = header.version.load(Ordering::Relaxed);
+        if version != T::VERSION {
+            return Err(QueueError::VersionMismatch {
+                got: version,
+                expected: T::VERSION,
+            });
+        }
+
+        let record_size = header.record_size.load(Ordering::Relaxed);
+        if record_size != Self::RECORD_SIZE as u32 {
+            return Err(QueueError::RecordSizeMismatch {
+                got: record_size,
+                expected: Self::RECORD_SIZE as u32,
+            });
+        }
+
+        let capacity = check_capacity(header.capacity.load(Ordering::Relaxed) as u64)?;
+        if let Some(expected) = expected_capacity
+            && capacity != expected {
+            return Err(QueueError::CapacityMismatch {
+                got: capacity as u32,
+                expected: expected as u32,
+            });
+        }
+
+        if file_len != Self::total_size(capacity) {
+            return Err(QueueError::InvalidSize {
+                got: file_len,
+                expected: Self::total_size(capacity),
+            });
+        }
+
+        // head and tail must describe a possible ring state
+        let producer_head = header.producer_head.load(Ordering::Acquire);
+        let consumer_tail = header.consumer_tail.load(Ordering::Acquire);
+        if consumer_tail > producer_head || producer_head - consumer_tail > capacity {
+            return Err(QueueError::CorruptedHeader {
+                producer_head,
+                consumer_tail,
+            });
+        }
+
+        Ok(capacity)
+    }
+
+    /// Get header reference - ZERO COST
+    /// the head and tail are atomics so a shared reference is all we ever need
+    #[inline(always)]
+    fn header(&self) -> &QueueHeader {
+        unsafe { &*self.header_ptr }
+    }
+
+    /// Get record at position - ZERO COST pointer arithmetic
+    #[inline(always)]
+    fn get_record(&self, pos: usize) -> T {
+        unsafe { ptr::read(self.records_ptr.add(pos)) }
+    }
+
+    /// Set record at position - ZERO COST pointer arithmetic
+    #[inline(always)]
+    fn set_record(&mut self, pos: usize, record: T) {
+        unsafe {
+            ptr::write(self.records_ptr.add(pos), record);
+        }
+    }
+
+    /// ULTRA-FAST dequeue - all pointers cached, no borrows
+    #[inline]
+    pub fn dequeue(&mut self) -> Result<Option<T>, QueueError> {
+        let header = self.header();
+
+        let producer_head = header.producer_head.load(Ordering::Acquire);
+        let consumer_tail = header.consumer_tail.load(Ordering::Relaxed);
+
+        if consumer_tail == producer_head {
+            return Ok(None);
+        }
+
+        let pos = (consumer_tail & self.mask) as usize;
+        std::sync::atomic::fence(Ordering::Acquire);
+        let record = self.get_record(pos);
+
+        self.header()
+            .consumer_tail
+            .store(consumer_tail + 1, Ordering::Release);
+
+        Ok(Some(record))
+    }
+
+    pub fn enqueue(&mut self, record: T) -> Result<(), QueueError> {
+        let header = self.header();
+
+        let consumer_tail = header.consumer_tail.load(Ordering::Acquire);
+        let producer_head = header.producer_head.load(Ordering::Relaxed);
+
+        let next_head = producer_head + 1;
+
+        if next_head - consumer_tail > self.capacity {
+            header.full_rejections.fetch_add(1, Ordering::Relaxed);
+            return Err(QueueError::QueueFull {
+                depth: next_head - consumer_tail,
+            });
+        }
+
+        let pos = (producer_head & self.mask) as usize;
+        self.set_record(pos, record);
+
+        self.header().producer_head.store(next_head, Ordering::Release);
+        self.record_high_watermark(next_head - consumer_tail);
+
+        Ok(())
+    }
+
+    /// Borrows the oldest unconsumed record in place , no copy out of the mmap.
+    /// The slot stays owned by the consumer until `commit()` , so the producer cannot overwrite it.
+    #[inline]
+    pub fn peek(&self) -> Option<&T> {
+        let header = self.header();
+
+        let producer_head = header.producer_head.load(Ordering::Acquire);
+        let consumer_tail = header.consumer_tail.load(Ordering::Relaxed);
+
+        if consumer_tail == producer_head {
+            return None;
+        }
+
+        let pos = (consumer_tail & self.mask) as usize;
+        std::sync::atomic::fence(Ordering::Acquire);
+        Some(unsafe { &*self.records_ptr.add(pos) })
+    }
+
+    /// Releases the record last returned by `peek()`. No-op on an empty queue.
+    #[inline]
+    pub fn commit(&mut self) {
+        let header = self.header();
+
+        let producer_head = header.producer_head.load(Ordering::Acquire);
+        let consumer_tail = header.consumer_tail.load(Ordering::Relaxed);
+
+        if consumer_tail != producer_head {
+            header.consumer_tail.store(consumer_tail + 1, Ordering::Release);
+        }
+    }
+
+    /// Visits records in place while `visit` returns true and consumes them.
+    /// The record `visit` returns false for is left in the queue. The tail is published
+    /// once at the end , so the producer only reclaims the slots after the last visit.
+    /// The visitor only gets a borrow for the duration of the call , a slot can be
+    /// overwritten by the producer as soon as it is consumed. Returns how many were consumed.
+    #[inline]
+    pub fn consume_while<F: FnMut(&T) -> bool>(&mut self, mut visit: F) -> usize {
+        let header = self.header();
+
+        let producer_head = header.producer_head.load(Ordering::Acquire);
+        let start_tail = header.consumer_tail.load(Ordering::Relaxed);
+
+        if start_tail == producer_head {
+            return 0;
+        }
+
+        std::sync::atomic::fence(Ordering::Acquire);
+        let mut tail = start_tail;
+        while tail != producer_head {
+            let pos = (tail & self.mask) as usize;
+            let record = unsafe { &*self.records_ptr.add(pos) };
+            if !visit(record) {
+                break;
+            }
+            tail += 1;
+        }
+
+        if tail != start_tail {
+            self.header().consumer_tail.store(tail, Ordering::Release);
+        }
+        (tail - start_tail) as usize
+    }
+
+    /// Dequeues up to `out.len()` records with a single tail publish.
+    /// Returns how many records were written to the front of `out`.
+    #[inline]
+    pub fn dequeue_batch(&mut self, out: &mut [T]) -> usize {
+        let header = self.header();
+
+        let producer_head = header.producer_head.load(Ordering::Acquire);
+        let consumer_tail = header.consumer_tail.load(Ordering::Relaxed);
+
+        let available = producer_head.saturating_sub(consumer_tail) as usize;
+        let count = available.min(out.len());
+        if count == 0 {
+            return 0;
+        }
+
+        std::sync::atomic::fence(Ordering::Acquire);
+        let pos = (consumer_tail & self.mask) as usize;
+        // the run can wrap past the end of the ring , copy it in at most two pieces
+        let first = count.min(self.capacity as usize - pos);
+        unsafe {
+            ptr::copy_nonoverlapping(self.records_ptr.add(pos), out.as_mut_ptr(), first);
+            ptr::copy_nonoverlapping(self.records_ptr, out.as_mut_ptr().add(first), count - first);
+        }
+
+        self.header()
+            .consumer_tail
+            .store(consumer_tail + count as u64, Ordering::Release);
+
+        count
+    }
+
+    /// Enqueues as many of `records` as fit with a single head publish.
+    /// Returns how many were enqueued (a prefix of `records`) , QueueFull if none fit.
+    #[inline]
+    pub fn enqueue_batch(&mut self, records: &[T]) -> Result<usize, QueueError> {
+        if records.is_empty() {
+            return Ok(0);
+        }
+
+        let header = self.header();
+
+        let consumer_tail = header.consumer_tail.load(Ordering::Acquire);
+        let producer_head = header.producer_head.load(Ordering::Relaxed);
+
+        let free = (self.capacity - producer_head.saturating_sub(consumer_tail)) as usize;
+        let count = free.min(records.len());
+        if count < records.len() {
+            header
+                .full_rejections
+                .fetch_add((records.len() - count) as u64, Ordering::Relaxed);
+        }
+        if count == 0 {
+            return Err(QueueError::QueueFull {
+                depth: producer_head - consumer_tail + 1,
+            });
+        }
+
+        let pos = (producer_head & self.mask) as usize;
+        let first = count.min(self.capacity as usize - pos);
+        unsafe {
+            ptr::copy_nonoverlapping(records.as_ptr(), self.records_ptr.add(pos), first);
+            ptr::copy_nonoverlapping(records.as_ptr().add(first), self.records_ptr, count - first);
+        }
+
+        self.header()
+            .producer_head
+            .store(producer_head + count as u64, Ordering::Release);
+        self.record_high_watermark(producer_head + count as u64 - consumer_tail);
+
+        Ok(count)
+    }
+
+    /// producer side only , a plain load and compare on the producer's own stats line
+    #[inline(always)]
+    fn record_high_watermark(&self, depth: u64) {
+        let header = self.header();
+        if depth > header.high_watermark.load(Ordering::Relaxed) {
+            header.high_watermark.store(depth, Ordering::Relaxed);
+        }
+    }
+
+    /// Stamp the producer heartbeat , call it regularly from the producing loop.
+    pub fn producer_heartbeat(&self) {
+        self.header()
+            .producer_heartbeat_ns
+            .store(unix_nanos(), Ordering::Relaxed);
+    }
+
+    /// Stamp the consumer heartbeat , call it regularly from the consuming loop.
+    pub fn consumer_heartbeat(&self) {
+        self.header()
+            .consumer_heartbeat_ns
+            .store(unix_nanos(), Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        let header = self.header();
+        let total_enqueued = header.producer_head.load(Ordering::Relaxed);
+        let total_dequeued = header.consumer_tail.load(Ordering::Relaxed);
+        QueueStats {
+            depth: total_enqueued.saturating_sub(total_dequeued),
+            capacity: self.capacity,
+            total_enqueued,
+            total_dequeued,
+            high_watermark: header.high_watermark.load(Ordering::Relaxed),
+            full_rejections: header.full_rejections.load(Ordering::Relaxed),
+            producer_heartbeat_ns: header.producer_heartbeat_ns.load(Ordering::Relaxed),
+            consumer_heartbeat_ns: header.consumer_heartbeat_ns.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn depth(&self) -> u64 {
+        let header = self.header();
+        let producer_head = header.producer_head.load(Ordering::Relaxed);
+        let consumer_tail = header.consumer_tail.load(Ordering::Relaxed);
+        producer_head.saturating_sub(consumer_tail)
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn flush(&self) -> Result<(), QueueError> {
+        self.mmap
+            .flush()
+            .map_err(|e| QueueError::Flush(e.to_string()))
+    }
+
+    pub fn dequeue_spin(&mut self, max_spins: usize) -> Result<Option<T>, QueueError> {
+        for _ in 0..max_spins {
+            match self.dequeue()? {
+                Some(record) => return Ok(Some(record)),
+                None => std::hint::spin_loop(),
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        self.wait
+    }
+
+    pub fn set_wait_strategy(&mut self, wait: WaitStrategy) {
+        self.wait = wait;
+    }
+
+    pub fn with_wait_strategy(mut self, wait: WaitStrategy) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    /// Waits up to `timeout` for a record using this queue's wait strategy.
+    /// Ok(None) means the timeout ran out with the queue still empty.
+    pub fn dequeue_timeout(&mut self, timeout: Duration) -> Result<Option<T>, QueueError> {
+        let deadline = Instant::now() + timeout;
+        let mut backoff = Backoff::new(self.wait);
+        loop {
+            if let Some(record) = self.dequeue()? {
+                return Ok(Some(record));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            backoff.snooze();
+        }
+    }
+
+    /// Waits until a record arrives , however long that takes.
+    pub fn dequeue_blocking(&mut self) -> Result<T, QueueError> {
+        let mut backoff = Backoff::new(self.wait);
+        loop {
+            if let Some(record) = self.dequeue()? {
+                return Ok(record);
+            }
+            backoff.snooze();
+        }
+    }
+}
+
+impl<T: ShmRecord> Drop for ShmQueue<T> {
+    fn drop(&mut self) {
+        // Flush before closing
+        let _ = self.mmap.flush();
+        // Unlock pages (memmap2 handles this automatically)
+        let _ = self.mmap.unlock();
+    }
+}
+
+/// Header fields of a queue file as they were at the moment of reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderSnapshot {
+    pub magic: u32,
+    pub version: u32,
+    pub record_size: u32,
+    pub producer_head: u64,
+    pub consumer_tail: u64,
+    pub stats: QueueStats,
+}
+
+/// Read-only mapping of a queue file for inspection (mmq).
+/// Never writes to the file and never moves head or tail , so it is safe to point at a live queue.
+/// The record type is not known up front , pick it from `header().magic`.
+pub struct QueueView {
+    mmap: Mmap,
+}
+
+impl QueueView {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, QueueError> {
+        let file = File::open(path).map_err(|e| QueueError::FileOpen(e.to_string()))?;
+        let file_len = file
+            .metadata()
+            .map(|metadata| metadata.len())
+            .map_err(|e| QueueError::FileStat(e.to_string()))?;
+        if file_len < HEADER_SIZE as u64 {
+            return Err(QueueError::InvalidSize {
+                got: file_len,
+                expected: HEADER_SIZE as u64,
+            });
+        }
+
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|e| QueueError::Mmap(e.to_string()))?;
+        Ok(QueueView { mmap })
+    }
+
+    fn raw_header(&self) -> &QueueHeader {
+        unsafe { &*(self.mmap.as_ptr() as *const QueueHeader) }
+    }
+
+    pub fn header(&self) -> HeaderSnapshot {
+        let header = self.raw_header();
+        // tail first , the same order the consumer side would see them in
+        let consumer_tail = header.consumer_tail.load(Ordering::Acquire);
+        let producer_head = header.producer_head.load(Ordering::Acquire);
+        HeaderSnapshot {
+            magic: header.magic.load(Ordering::Relaxed),
+            version: header.version.load(Ordering::Relaxed),
+            record_size: header.record_size.load(Ordering::Relaxed),
+            producer_head,
+            consumer_tail,
+            stats: QueueStats {
+                depth: producer_head.saturating_sub(consumer_tail),
+                capacity: header.capacity.load(Ordering::Relaxed) as u64,
+                total_enqueued: producer_head,
+                total_dequeued: consumer_tail,
+                high_watermark: header.high_watermark.load(Ordering::Relaxed),
+                full_rejections: header.full_rejections.load(Ordering::Relaxed),
+                producer_heartbeat_ns: header.producer_heartbeat_ns.load(Ordering::Relaxed),
+                consumer_heartbeat_ns: header.consumer_heartbeat_ns.load(Ordering::Relaxed),
+            },
+        }
+    }
+
+    /// checks that the file really holds `T` records , same checks as `ShmQueue::open`
+    /// minus the head / tail sanity check , a corrupted header is exactly what you want to look at
+    pub fn check<T: ShmRecord>(&self) -> Result<(), QueueError> {
+        let header = self.header();
+        if header.magic != T::MAGIC {
+            return Err(QueueError::InvalidMagic { got: header.magic, expected: T::MAGIC });
+        }
+        if header.version != T::VERSION {
+            return Err(QueueError::VersionMismatch { got: header.version, expected: T::VERSION });
+        }
+        if header.record_size != ShmQueue::<T>::RECORD_SIZE as u32 {
+            return Err(QueueError::RecordSizeMismatch {
+                got: header.record_size,
+                expected: ShmQueue::<T>::RECORD_SIZE as u32,
+            });
+        }
+        let capacity = check_capacity(header.stats.capacity)?;
+        let expected = ShmQueue::<T>::total_size(capacity);
+        if self.mmap.len() as u64 != expected {
+            return Err(QueueError::InvalidSize { got: self.mmap.len() as u64, expected });
+        }
+        Ok(())
+    }
+
+    /// record at the absolute ring index `index` (the value head / tail count in)
+    /// a slot the producer is writing right now can come back torn , this is a debugging aid
+    pub fn record<T: ShmRecord>(&self, index: u64) -> Result<T, QueueError> {
+        self.check::<T>()?;
+        let pos = (index & (self.header().stats.capacity - 1)) as usize;
+        let offset = HEADER_SIZE + pos * ShmQueue::<T>::RECORD_SIZE;
+        Ok(unsafe { ptr::read_unaligned(self.mmap.as_ptr().add(offset) as *const T) })
+    }
+}
+
+// Error types
+#[derive(Debug , Clone)]
+pub enum QueueError {
+    FileOpen(String),
+    FileStat(String),
+    InvalidSize { got: u64, expected: u64 },
+    Mmap(String),
+    InvalidMagic { got: u32, expected: u32 },
+    CapacityMismatch { got: u32, expected: u32 },
+    InvalidCapacity { got: u64 },
+    VersionMismatch { got: u32, expected: u32 },
+    RecordSizeMismatch { got: u32, expected: u32 },
+    CorruptedOrder,
+    CorruptedHeader { producer_head: u64, consumer_tail: u64 },
+    QueueFull { depth: u64 },
+    Flush(String),
+    // not fatal , open_or_create wiped an unusable file and started from an empty queue
+    Reinitialized { cause: Box<QueueError> },
+}
+
+impl QueueError {
+    /// errors that mean the file carries our magic , version and record size but cannot be a valid ring ,
+    /// the only ones open_or_create wipes
+    pub fn is_corruption(&self) -> bool {
+        matches!(
+            self,
+            QueueError::InvalidSize { .. }
+                | QueueError::InvalidCapacity { .. }
+                | QueueError::CorruptedHeader { .. }
+        )
+    }
+}
+
+impl std::fmt::Display for QueueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueueError::FileOpen(e) => write!(f, "Failed to open file: {}", e),
+            QueueError::FileStat(e) => write!(f, "Failed to stat file: {}", e),
+            QueueError::InvalidSize { got, expected } => {
+                write!(f, "Invalid file size: got {}, expected {}", got, expected)
+            }
+            QueueError::Mmap(e) => write!(f, "Failed to mmap: {}", e),
+            QueueError::InvalidMagic { got, expected } => {
+                write!(f, "Invalid queue magic: got 0x{:X}, expected 0x{:X} (wrong queue file?)", got, expected)
+            }
+            QueueError::CapacityMismatch { got, expected } => {
+                write!(f, "Capacity mismatch: got {}, expected {}", got, expected)
+            }
+            QueueError::InvalidCapacity { got } => {
+                write!(f, "Invalid capacity {}: must be a power of two up to 2^31", got)
+            }
+            QueueError::VersionMismatch { got, expected } => {
+                write!(f, "Record layout version mismatch: got {}, expected {}", got, expected)
+            }
+            QueueError::RecordSizeMismatch { got, expected } => {
+                write!(f, "Record size mismatch: got {} bytes, expected {}", got, expected)
+            }
+            QueueError::CorruptedOrder => write!(f, "Corrupted order detected"),
+            QueueError::CorruptedHeader { producer_head, consumer_tail } => {
+                write!(f, "Corrupted queue header: head {}, tail {}", producer_head, consumer_tail)
+            }
+            QueueError::QueueFull { depth } => {
+                write!(f, "Queue full - backpressure at depth {}", depth)
+            }
+            QueueError::Flush(e) => write!(f, "Failed to flush: {}", e),
+            QueueError::Reinitialized { cause } => {
+                write!(f, "Queue file was reinitialized: {}", cause)
+            }
+        }
+    }
+}
+
+impl std::error::Error for QueueError {}
+
+// Thread-safe: Queue can be sent between threads
+unsafe impl<T: ShmRecord> Send for ShmQueue<T> {}
+// Not Sync: only one thread should access at a time (SPSC model)
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::shm::feed_queue_mm::MarketMakerFeed;
+    use crate::shm::order_queue_mm::MmOrder;
+
+    // a file per test and process , removed again when the test is done
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("mm_queue_test_{}_{}", std::process::id(), name));
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn order(client_id: u64) -> MmOrder {
+        MmOrder { client_id, ..Default::default() }
+    }
+
+    #[test]
+    fn open_or_create_keeps_a_valid_queue() {
+        let path = TempPath::new("keep");
+        let mut queue = ShmQueue::<MmOrder>::create(&path.0, 8).unwrap();
+        queue.enqueue(order(1)).unwrap();
+        queue.enqueue(order(2)).unwrap();
+        drop(queue);
+
+        let (mut queue, reinit) = ShmQueue::<MmOrder>::open_or_create(&path.0, 8).unwrap();
+        assert!(reinit.is_none());
+        assert_eq!(queue.dequeue().unwrap().unwrap().client_id, 1);
+        assert_eq!(queue.dequeue().unwrap().unwrap().client_id, 2);
+    }
+
+    #[test]
+    fn open_or_create_leaves_another_record_type_alone() {
+        let path = TempPath::new("foreign");
+        let mut feeds = ShmQueue::<MarketMakerFeed>::create(&path.0, 8).unwrap();
+        feeds.enqueue(MarketMakerFeed { seq: 7, ..Default::default() }).unwrap();
+
+        let error = ShmQueue::<MmOrder>::open_or_create(&path.0, 8).err().unwrap();
+        assert!(matches!(error, QueueError::InvalidMagic { .. }));
+        // the feed ring the peer still has mapped is untouched
+        assert_eq!(feeds.depth(), 1);
+        assert_eq!(feeds.dequeue().unwrap().unwrap().seq, 7);
+    }
+
+    #[test]
+    fn open_or_create_leaves_another_capacity_alone() {
+        let path = TempPath::new("capacity");
+        let mut queue = ShmQueue::<MmOrder>::create(&path.0, 16).unwrap();
+        queue.enqueue(order(1)).unwrap();
+
+        let error = ShmQueue::<MmOrder>::open_or_create(&path.0, 8).err().unwrap();
+        assert!(matches!(error, QueueError::CapacityMismatch { got: 16, expected: 8 }));
+        assert_eq!(queue.depth(), 1);
+    }
+
+    #[test]
+    fn open_or_create_reinitializes_a_corrupt_header() {
+        let path = TempPath::new("corrupt");
+        let queue = ShmQueue::<MmOrder>::create(&path.0, 8).unwrap();
+        queue.header().consumer_tail.store(5, Ordering::SeqCst);
+        drop(queue);
+
+        let (queue, reinit) = ShmQueue::<MmOrder>::open_or_create(&path.0, 8).unwrap();
+        match reinit {
+            Some(QueueError::Reinitialized { cause }) => assert!(matches!(*cause, QueueError::CorruptedHeader { .. })),
+            other => panic!("expected a reinit , got {:?}", other),
+        }
+        assert_eq!(queue.depth(), 0);
+    }
+
+    #[test]
+    fn open_or_create_reinitializes_a_truncated_file() {
+        let path = TempPath::new("truncated");
+        std::fs::write(&path.0, [0u8; 16]).unwrap();
+
+        let (queue, reinit) = ShmQueue::<MmOrder>::open_or_create(&path.0, 8).unwrap();
+        assert!(matches!(reinit, Some(QueueError::Reinitialized { .. })));
+        assert_eq!(queue.capacity(), 8);
+    }
+
+    fn orders(ids: std::ops::Range<u64>) -> Vec<MmOrder> {
+        ids.map(order).collect()
+    }
+
+    #[test]
+    fn batches_wrap_around_the_end_of_the_ring() {
+        let path = TempPath::new("wrap");
+        let mut queue = ShmQueue::<MmOrder>::create(&path.0, 8).unwrap();
+        let mut out = [MmOrder::default(); 8];
+
+        // move head and tail to slot 6 so the next run is split 2 + 3
+        assert_eq!(queue.enqueue_batch(&orders(0..6)).unwrap(), 6);
+        assert_eq!(queue.dequeue_batch(&mut out), 6);
+        assert_eq!(queue.enqueue_batch(&orders(10..15)).unwrap(), 5);
+        assert_eq!(queue.depth(), 5);
+
+        let count = queue.dequeue_batch(&mut out);
+        assert_eq!(count, 5);
+        let ids: Vec<u64> = out[..count].iter().map(|record| record.client_id).collect();
+        assert_eq!(ids, vec![10, 11, 12, 13, 14]);
+
+        // consume_while walks the same split in place
+        assert_eq!(queue.enqueue_batch(&orders(20..27)).unwrap(), 7);
+        let mut seen = Vec::new();
+        assert_eq!(queue.consume_while(|record| { seen.push(record.client_id); record.client_id < 24 }), 4);
+        assert_eq!(seen, vec![20, 21, 22, 23, 24]);
+        assert_eq!(queue.dequeue().unwrap().unwrap().client_id, 24);
+        assert_eq!(queue.depth(), 2);
+    }
+
+    #[test]
+    fn batch_into_a_nearly_full_ring_sends_a_prefix() {
+        let path = TempPath::new("partial");
+        let mut queue = ShmQueue::<MmOrder>::create(&path.0, 8).unwrap();
+
+        assert_eq!(queue.enqueue_batch(&orders(0..6)).unwrap(), 6);
+        assert_eq!(queue.enqueue_batch(&orders(6..10)).unwrap(), 2);
+        assert_eq!(queue.stats().full_rejections, 2);
+
+        // nothing fits any more
+        assert!(matches!(queue.enqueue_batch(&orders(10..11)), Err(QueueError::QueueFull { depth: 9 })));
+        assert!(matches!(queue.enqueue(order(11)), Err(QueueError::QueueFull { depth: 9 })));
+        assert_eq!(queue.stats().full_rejections, 4);
+
+        let mut out = [MmOrder::default(); 16];
+        let count = queue.dequeue_batch(&mut out);
+        let ids: Vec<u64> = out[..count].iter().map(|record| record.client_id).collect();
+        assert_eq!(ids, (0..8).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn high_watermark_keeps_the_peak_depth() {
+        let path = TempPath::new("watermark");
+        let mut queue = ShmQueue::<MmOrder>::create(&path.0, 8).unwrap();
+
+        queue.enqueue_batch(&orders(0..3)).unwrap();
+        queue.enqueue(order(3)).unwrap();
+        assert_eq!(queue.stats().high_watermark, 4);
+
+        let mut out = [MmOrder::default(); 8];
+        queue.dequeue_batch(&mut out);
+        queue.enqueue_batch(&orders(4..6)).unwrap();
+        assert_eq!(queue.stats().high_watermark, 4);
+
+        queue.enqueue_batch(&orders(6..20)).unwrap();
+        assert_eq!(queue.stats().high_watermark, 8);
+        assert_eq!(queue.stats().full_rejections, 8);
+    }
+}
@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+// how a consumer waits when its queue is empty
+// busy spinning gives the lowest latency but burns a full core even when nothing is live ,
+// the backoff strategies give that core back on quiet queues and test boxes
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitStrategy {
+    /// spin_loop hint forever , lowest latency , 100% cpu
+    BusySpin,
+    /// spin for `spins` rounds then `thread::yield_now` on every later round
+    Yield { spins: u32 },
+    /// spin , then yield , then sleep `sleep` per round once both budgets are used up
+    Backoff { spins: u32, yields: u32, sleep: Duration },
+}
+
+impl Default for WaitStrategy {
+    fn default() -> Self {
+        WaitStrategy::Backoff {
+            spins: 128,
+            yields: 64,
+            sleep: Duration::from_micros(50),
+        }
+    }
+}
+
+/// Per-wait state for a `WaitStrategy` , `reset()` as soon as there is work again.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    strategy: WaitStrategy,
+    step: u32,
+}
+
+impl Backoff {
+    pub fn new(strategy: WaitStrategy) -> Self {
+        Self { strategy, step: 0 }
+    }
+
+    #[inline(always)]
+    pub fn reset(&mut self) {
+        self.step = 0;
+    }
+
+    /// wait one round , every call escalates a little further along the strategy
+    #[inline]
+    pub fn snooze(&mut self) {
+        match self.strategy {
+            WaitStrategy::BusySpin => std::hint::spin_loop(),
+            WaitStrategy::Yield { spins } => {
+                if self.step < spins {
+                    std::hint::spin_loop();
+                } else {
+                    std::thread::yield_now();
+                }
+            }
+            WaitStrategy::Backoff { spins, yields, sleep } => {
+                if self.step < spins {
+                    std::hint::spin_loop();
+                } else if self.step < spins.saturating_add(yields) {
+                    std::thread::yield_now();
+                } else {
+                    std::thread::sleep(sleep);
+                }
+            }
+        }
+        self.step = self.step.saturating_add(1);
+    }
+}
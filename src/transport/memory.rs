@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::shm::queue::QueueError;
+use crate::transport::{Sink, Source};
+
+/// In process queue with the same semantics as the shared memory ring (bounded , FIFO ,
+/// partial batch sends) but no files behind it.
+/// Clones share the same buffer , hand one clone to the market maker and keep the other
+/// to push feeds / fills / api messages in and read the orders it sent.
+#[derive(Debug)]
+pub struct MemQueue<T> {
+    records: Arc<Mutex<VecDeque<T>>>,
+    capacity: usize,
+}
+
+impl<T> Clone for MemQueue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            records: Arc::clone(&self.records),
+            capacity: self.capacity,
+        }
+    }
+}
+
+impl<T> MemQueue<T> {
+    /// `capacity` works like the ring's , sends past it fail with QueueFull
+    /// tests can make it tiny to hit the full paths
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: Arc::new(Mutex::new(VecDeque::with_capacity(capacity.min(4096)))),
+            capacity,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<T>> {
+        // a panic on the other side of a test does not make the records any less valid
+        self.records.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn push(&self, record: T) -> Result<(), QueueError> {
+        let mut records = self.lock();
+        if records.len() >= self.capacity {
+            return Err(QueueError::QueueFull { depth: records.len() as u64 });
+        }
+        records.push_back(record);
+        Ok(())
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        self.lock().pop_front()
+    }
+
+    /// takes everything that is queued right now
+    pub fn drain(&self) -> Vec<T> {
+        self.lock().drain(..).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<T: Copy> Source<T> for MemQueue<T> {
+    fn recv_batch(&mut self, out: &mut [T]) -> usize {
+        let mut records = self.lock();
+        let n = out.len().min(records.len());
+        for (slot, record) in out.iter_mut().zip(records.drain(..n)) {
+            *slot = record;
+        }
+        n
+    }
+
+    fn consume_while<F: FnMut(&T) -> bool>(&mut self, mut visit: F) -> usize {
+        let mut records = self.lock();
+        let mut consumed = 0;
+        while let Some(record) = records.front() {
+            if !visit(record) {
+                break;
+            }
+            records.pop_front();
+            consumed += 1;
+        }
+        consumed
+    }
+}
+
+impl<T: Copy> Sink<T> for MemQueue<T> {
+    fn send_batch(&mut self, records: &[T]) -> Result<usize, QueueError> {
+        if records.is_empty() {
+            return Ok(0);
+        }
+        let mut queued = self.lock();
+        let free = self.capacity.saturating_sub(queued.len());
+        if free == 0 {
+            return Err(QueueError::QueueFull { depth: queued.len() as u64 });
+        }
+        let n = free.min(records.len());
+        queued.extend(&records[..n]);
+        Ok(n)
+    }
+}
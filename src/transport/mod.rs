@@ -0,0 +1,58 @@
+// where the market maker reads its inputs from and writes its orders to
+// the bot only talks to these traits , the shared memory queues are one implementation
+// and MemQueue (in process , no files) is the other one , for tests and simulation
+
+use crate::shm::{
+    feed_queue_mm::MarketMakerFeed,
+    fill_queue_mm::MarketMakerFill,
+    order_queue_mm::MmOrder,
+    queue::QueueError,
+    response_queue_mm::MessageFromApi,
+};
+
+pub mod memory;
+pub mod shm;
+
+pub use memory::MemQueue;
+
+/// Consumer end of a record stream.
+pub trait Source<T> {
+    /// moves up to `out.len()` records into `out` , returns how many
+    fn recv_batch(&mut self, out: &mut [T]) -> usize;
+
+    /// hands records to `visit` in order without taking them out first ,
+    /// stops at the first record `visit` returns false for (that one stays queued)
+    /// returns how many records were consumed
+    fn consume_while<F: FnMut(&T) -> bool>(&mut self, visit: F) -> usize;
+
+    /// tells the other side we are still reading , no-op where nobody is watching
+    fn heartbeat(&self) {}
+}
+
+/// Producer end of a record stream.
+pub trait Sink<T> {
+    /// sends the longest prefix of `records` that fits , returns how many went out
+    /// QueueFull only if not even the first one fit
+    fn send_batch(&mut self, records: &[T]) -> Result<usize, QueueError>;
+
+    fn send(&mut self, record: T) -> Result<(), QueueError> {
+        self.send_batch(std::slice::from_ref(&record)).map(|_| ())
+    }
+
+    /// tells the other side we are still writing , no-op where nobody is watching
+    fn heartbeat(&self) {}
+}
+
+// one name per stream the bot uses , anything that moves the right record type qualifies
+
+pub trait FeedSource: Source<MarketMakerFeed> {}
+impl<S: Source<MarketMakerFeed>> FeedSource for S {}
+
+pub trait FillSource: Source<MarketMakerFill> {}
+impl<S: Source<MarketMakerFill>> FillSource for S {}
+
+pub trait ApiSource: Source<MessageFromApi> {}
+impl<S: Source<MessageFromApi>> ApiSource for S {}
+
+pub trait OrderSink: Sink<MmOrder> {}
+impl<S: Sink<MmOrder>> OrderSink for S {}
@@ -0,0 +1,36 @@
+use crate::shm::queue::{QueueError, ShmQueue, ShmRecord};
+use crate::transport::{Sink, Source};
+
+// the shared memory ring is both ends , which one a process uses depends on which side of the engine it is
+
+impl<T: ShmRecord> Source<T> for ShmQueue<T> {
+    #[inline]
+    fn recv_batch(&mut self, out: &mut [T]) -> usize {
+        self.dequeue_batch(out)
+    }
+
+    #[inline]
+    fn consume_while<F: FnMut(&T) -> bool>(&mut self, visit: F) -> usize {
+        ShmQueue::consume_while(self, visit)
+    }
+
+    fn heartbeat(&self) {
+        self.consumer_heartbeat();
+    }
+}
+
+impl<T: ShmRecord> Sink<T> for ShmQueue<T> {
+    #[inline]
+    fn send_batch(&mut self, records: &[T]) -> Result<usize, QueueError> {
+        self.enqueue_batch(records)
+    }
+
+    #[inline]
+    fn send(&mut self, record: T) -> Result<(), QueueError> {
+        self.enqueue(record)
+    }
+
+    fn heartbeat(&self) {
+        self.producer_heartbeat();
+    }
+}